@@ -5,10 +5,13 @@ curl --location --request POST 'localhost:3030/api/post_job' \
     --header 'Content-Type: application/json' \
     --header 'Content-Type: text/plain' \
     --data-raw '{
-        "job_location": "Somewhere",
+        "job_location": "ul. Długa 5, 31-147 Kraków",
         "contract_type": "Zlecenie",
         "mode": "Mobile",
         "hours": "Week",
         "tags": ["Week", "Mobile", "Zlecenie", "random"],
-        "description": "A test job listing for testing"
+        "description": "A test job listing for testing",
+        "pay_amount": 3200,
+        "pay_unit": "Hour",
+        "pay_gross": true
     }'
\ No newline at end of file
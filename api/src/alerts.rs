@@ -0,0 +1,376 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use sqlx::{types::Json, Pool, Postgres};
+use uuid::Uuid;
+
+use crate::error::Error;
+use crate::jobs::{ContractType, Job, JobHours, JobMode, JobQuery, PayUnit};
+use crate::mail::send_mail;
+use crate::notifications::{notify, Event};
+use crate::tags::{canonical_names, tag_strings};
+
+/// Instant alerts still unmailed after this are taken to have failed and go into the digest
+const INSTANT_RETRY_AFTER: time::Duration = time::Duration::hours(1);
+
+#[derive(Serialize, Deserialize, Debug, sqlx::Type, Clone, PartialEq)]
+#[sqlx(type_name = "alert_frequency", rename_all = "lowercase")]
+pub enum AlertFrequency {
+    Instant,
+    Daily,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SaveSearchRequest {
+    name: String,
+    /// Stored with canonical tags, so that new jobs can be matched without the database
+    query: JobQuery,
+    frequency: AlertFrequency,
+}
+impl SaveSearchRequest {
+    pub async fn execute(&self, pool: &Pool<Postgres>, userid: Uuid) -> Result<SavedSearch, Error> {
+        self.query.validate()?;
+        let query = self.query.canonical(pool).await?;
+        Ok(sqlx::query_as!(
+            SavedSearch,
+            r#"INSERT INTO saved_searches (userid, name, query, frequency, creation_time)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING
+                searchid,
+                userid,
+                name,
+                query "query: Json<JobQuery>",
+                frequency "frequency: AlertFrequency",
+                creation_time"#,
+            userid,
+            self.name,
+            Json(&query) as _,
+            self.frequency.clone() as _,
+            time::OffsetDateTime::now_utc()
+        )
+        .fetch_one(pool)
+        .await?)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SavedSearch {
+    searchid: i32,
+    userid: Uuid,
+    name: String,
+    query: Json<JobQuery>,
+    frequency: AlertFrequency,
+    creation_time: time::OffsetDateTime,
+}
+
+pub async fn get_saved_searches(
+    pool: &Pool<Postgres>,
+    userid: Uuid,
+) -> Result<Vec<SavedSearch>, sqlx::Error> {
+    sqlx::query_as!(
+        SavedSearch,
+        r#"SELECT
+            searchid,
+            userid,
+            name,
+            query "query: Json<JobQuery>",
+            frequency "frequency: AlertFrequency",
+            creation_time
+        FROM saved_searches
+        WHERE userid = $1
+        ORDER BY creation_time"#,
+        userid
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchRequest {
+    searchid: i32,
+}
+impl SearchRequest {
+    pub async fn delete(&self, pool: &Pool<Postgres>, userid: Uuid) -> Result<(), Error> {
+        let deleted = sqlx::query!(
+            "DELETE FROM saved_searches WHERE searchid = $1 AND userid = $2",
+            self.searchid,
+            userid
+        )
+        .execute(pool)
+        .await?;
+        if deleted.rows_affected() == 0 {
+            return Err(Error::Forbidden);
+        }
+        Ok(())
+    }
+    /// Jobs found by the search since it was saved, newest first
+    pub async fn get_matches(
+        &self,
+        pool: &Pool<Postgres>,
+        userid: Uuid,
+    ) -> Result<Vec<Job>, sqlx::Error> {
+        sqlx::query_as!(
+            Job,
+            r#"SELECT
+                jobs.jobid,
+                jobs.owner,
+                jobs.creation_time,
+                jobs.job_location,
+                jobs.contract_type "contract_type: ContractType",
+                jobs.mode "mode: JobMode",
+                jobs.hours "hours: JobHours",
+                jobs.description,
+                jobs.tags,
+                jobs.pay_amount,
+                jobs.pay_unit "pay_unit: PayUnit",
+                jobs.pay_currency,
+                jobs.pay_gross,
+                jobs.city,
+                jobs.voivodeship,
+                jobs.postal_code,
+                jobs.latitude,
+                jobs.longitude,
+                jobs.expires_at,
+                jobs.archived_at
+            FROM search_matches
+            JOIN saved_searches ON saved_searches.searchid = search_matches.searchid
+            JOIN jobs ON jobs.jobid = search_matches.jobid
+            WHERE search_matches.searchid = $1 AND saved_searches.userid = $2
+            ORDER BY search_matches.creation_time DESC"#,
+            self.searchid,
+            userid
+        )
+        .fetch_all(pool)
+        .await
+    }
+}
+
+/// Replaces the tags of every saved search with their current canonical names,
+/// needed when a synonym merges a tag into another
+pub async fn canonicalize_saved_searches(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    let searches =
+        sqlx::query!(r#"SELECT searchid, query "query: Json<JobQuery>" FROM saved_searches"#)
+            .fetch_all(pool)
+            .await?;
+    for search in searches {
+        let query = search.query.canonical(pool).await?;
+        sqlx::query!(
+            "UPDATE saved_searches SET query = $2 WHERE searchid = $1",
+            search.searchid,
+            Json(&query) as _
+        )
+        .execute(pool)
+        .await?;
+    }
+    Ok(())
+}
+
+fn describe_job(jobid: i32, location: Option<&str>, description: Option<&str>) -> String {
+    format!(
+        "#{} {}\n{}\n",
+        jobid,
+        location.unwrap_or_default(),
+        description.unwrap_or_default()
+    )
+}
+
+/// Records a match of the search and alerts its owner, instant searches are mailed right away
+async fn deliver_alert(
+    pool: &Pool<Postgres>,
+    searchid: i32,
+    userid: Uuid,
+    name: &str,
+    email: &str,
+    instant: bool,
+    job: &Job,
+) -> Result<(), Error> {
+    sqlx::query!(
+        r#"INSERT INTO search_matches (searchid, jobid, creation_time, mailed)
+        VALUES ($1, $2, $3, false)
+        ON CONFLICT DO NOTHING"#,
+        searchid,
+        job.jobid,
+        time::OffsetDateTime::now_utc()
+    )
+    .execute(pool)
+    .await?;
+    notify(
+        pool,
+        userid,
+        Event::JobAlert {
+            searchid,
+            jobid: job.jobid,
+        },
+    )
+    .await;
+    if instant {
+        send_mail(
+            email,
+            &format!("New job for \"{}\"", name),
+            describe_job(
+                job.jobid,
+                job.job_location.as_deref(),
+                job.description.as_deref(),
+            ),
+        )
+        .await?;
+        mark_mailed(pool, &[searchid], &[job.jobid]).await?;
+    }
+    Ok(())
+}
+
+/// Finds the saved searches matching a newly published job and delivers their alerts.
+/// Instant searches are mailed right away, daily ones wait for `send_digests`.
+/// A failed alert is logged and doesn't stop the others
+pub async fn match_job(pool: &Pool<Postgres>, job: &Job) -> Result<(), Error> {
+    let mut job = job.clone();
+    let tags = canonical_names(pool, &tag_strings(&job.tags)).await?;
+    job.tags = Some(tags.clone().into());
+    // Searches that can't match the job's type, mode, hours or tags are left out here,
+    // the remaining filters are checked by `JobQuery::matches`
+    let searches = sqlx::query!(
+        r#"SELECT
+            saved_searches.searchid,
+            saved_searches.userid,
+            saved_searches.name,
+            saved_searches.query "query: Json<JobQuery>",
+            saved_searches.frequency "frequency: AlertFrequency",
+            users.email
+        FROM saved_searches
+        JOIN users ON users.userid = saved_searches.userid
+        WHERE (coalesce(query->'contract_type', 'null') IN ('null', '[]') OR
+                query->'contract_type' @> $1) AND
+            (coalesce(query->'mode', 'null') IN ('null', '[]') OR query->'mode' @> $2) AND
+            (coalesce(query->'hours', 'null') IN ('null', '[]') OR query->'hours' @> $3) AND
+            query->'tags' <@ $4 AND
+            (coalesce(query->'any_tags', '[]') = '[]' OR query->'any_tags' ?| $5) AND
+            NOT coalesce(query->'exclude_tags', '[]') ?| $5"#,
+        Json(&job.contract_type) as _,
+        Json(&job.mode) as _,
+        Json(&job.hours) as _,
+        Json(&tags) as _,
+        &tags
+    )
+    .fetch_all(pool)
+    .await?;
+
+    for search in searches {
+        if !search.query.matches(&job) {
+            continue;
+        }
+        let delivered = deliver_alert(
+            pool,
+            search.searchid,
+            search.userid,
+            &search.name,
+            &search.email,
+            search.frequency == AlertFrequency::Instant,
+            &job,
+        )
+        .await;
+        if let Err(e) = delivered {
+            println!("Job alert error for search {}: {}", search.searchid, e);
+        }
+    }
+    Ok(())
+}
+
+/// Runs `match_job` in the background, so publishing a job neither waits for its alerts
+/// nor fails with them
+pub fn spawn_match_job(pool: &Pool<Postgres>, job: &Job) {
+    let pool = pool.clone();
+    let job = job.clone();
+    tokio::spawn(async move {
+        if let Err(e) = match_job(&pool, &job).await {
+            println!("Job alert error: {}", e);
+        }
+    });
+}
+
+async fn mark_mailed(
+    pool: &Pool<Postgres>,
+    searchids: &[i32],
+    jobids: &[i32],
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE search_matches SET mailed = true
+        FROM unnest($1::int4[], $2::int4[]) sent(searchid, jobid)
+        WHERE search_matches.searchid = sent.searchid AND search_matches.jobid = sent.jobid"#,
+        searchids,
+        jobids
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// One user's digest and the matches it lists, as pairs of `searchids` and `jobids`
+#[derive(Default)]
+struct Digest {
+    email: String,
+    body: String,
+    searchids: Vec<i32>,
+    jobids: Vec<i32>,
+}
+
+async fn send_digest(pool: &Pool<Postgres>, digest: Digest) -> Result<(), Error> {
+    send_mail(&digest.email, "Your daily job alerts", digest.body).await?;
+    mark_mailed(pool, &digest.searchids, &digest.jobids).await?;
+    Ok(())
+}
+
+/// Mails every user one digest of the matches their daily searches found since the last one,
+/// along with the instant alerts whose mail failed. Matches are marked mailed only once their
+/// digest is sent, so a failed digest is retried next time
+pub async fn send_digests(pool: &Pool<Postgres>) -> Result<(), Error> {
+    let matches = sqlx::query!(
+        r#"SELECT
+            users.userid,
+            users.email,
+            saved_searches.name,
+            search_matches.searchid,
+            jobs.jobid,
+            jobs.job_location,
+            jobs.description
+        FROM search_matches
+        JOIN saved_searches ON saved_searches.searchid = search_matches.searchid
+        JOIN users ON users.userid = saved_searches.userid
+        JOIN jobs ON jobs.jobid = search_matches.jobid
+        WHERE NOT search_matches.mailed AND
+            (saved_searches.frequency = 'daily' OR search_matches.creation_time < $1)
+        ORDER BY saved_searches.name, jobs.creation_time"#,
+        time::OffsetDateTime::now_utc() - INSTANT_RETRY_AFTER
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut digests: BTreeMap<Uuid, Digest> = BTreeMap::new();
+    for m in matches {
+        let digest = digests.entry(m.userid).or_default();
+        digest.email = m.email;
+        digest.body.push_str(&format!("[{}] ", m.name));
+        digest.body.push_str(&describe_job(
+            m.jobid,
+            Some(&m.job_location),
+            m.description.as_deref(),
+        ));
+        digest.searchids.push(m.searchid);
+        digest.jobids.push(m.jobid);
+    }
+    for (userid, digest) in digests {
+        if let Err(e) = send_digest(pool, digest).await {
+            println!("Digest error for user {}: {}", userid, e);
+        }
+    }
+    Ok(())
+}
+
+pub async fn digest_task(pool: Pool<Postgres>) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(24 * 60 * 60));
+    loop {
+        interval.tick().await;
+        if let Err(e) = send_digests(&pool).await {
+            println!("Digest error: {}", e);
+        }
+    }
+}
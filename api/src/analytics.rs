@@ -0,0 +1,356 @@
+use hmac::{Hmac, Mac};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use sqlx::{Pool, Postgres};
+use time::{Date, Duration, OffsetDateTime};
+use uuid::Uuid;
+
+use crate::error::Error;
+use crate::jobs::{get_job, Job};
+use crate::offers::Client;
+use crate::users::iso_date;
+
+/// Used when a request has no `from`
+const DEFAULT_RANGE: Duration = Duration::days(30);
+const MAX_RANGE: Duration = Duration::days(366);
+const PRUNE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+lazy_static! {
+    /// Key of the anonymous viewer hashes, made at startup and never stored, so after a restart
+    /// an anonymous viewer may be counted again that day
+    static ref VIEWER_KEY: Vec<u8> = [Uuid::new_v4(), Uuid::new_v4()]
+        .iter()
+        .flat_map(|u| *u.as_bytes())
+        .collect();
+}
+
+/// What a counter of `job_stats_daily` counts
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Metric {
+    Impression,
+    View,
+    Save,
+    Application,
+}
+
+fn today() -> Date {
+    OffsetDateTime::now_utc().date()
+}
+
+/// Adds one to the metric of each job for today
+async fn record(pool: &Pool<Postgres>, jobids: &[i32], metric: Metric) -> Result<(), sqlx::Error> {
+    let one = |m: Metric| i32::from(m == metric);
+    sqlx::query!(
+        r#"INSERT INTO job_stats_daily (jobid, day, impressions, views, saves, applications)
+        SELECT jobid, $2, $3, $4, $5, $6 FROM unnest($1::int4[]) jobid
+        ON CONFLICT (jobid, day) DO UPDATE SET
+            impressions = job_stats_daily.impressions + excluded.impressions,
+            views = job_stats_daily.views + excluded.views,
+            saves = job_stats_daily.saves + excluded.saves,
+            applications = job_stats_daily.applications + excluded.applications"#,
+        jobids,
+        today(),
+        one(Metric::Impression),
+        one(Metric::View),
+        one(Metric::Save),
+        one(Metric::Application)
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Runs `record` in the background, the counters never fail or hold up a request
+pub fn spawn_record(pool: &Pool<Postgres>, jobids: Vec<i32>, metric: Metric) {
+    let pool = pool.clone();
+    tokio::spawn(async move {
+        if let Err(e) = record(&pool, &jobids, metric).await {
+            println!("Job stats error: {}", e);
+        }
+    });
+}
+
+/// Salted with the day, so an address hashes differently each day and can't be tracked
+/// across days or recovered without the key
+fn viewer_hash(ip: &str, day: Date) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(&VIEWER_KEY).expect("HMAC takes keys of any length");
+    mac.update(day.to_string().as_bytes());
+    mac.update(ip.as_bytes());
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+async fn count_view(
+    pool: &Pool<Postgres>,
+    jobid: i32,
+    day: Date,
+    viewer: &str,
+) -> Result<(), sqlx::Error> {
+    let seen = sqlx::query!(
+        r#"INSERT INTO job_views_seen (jobid, day, viewer)
+        VALUES ($1, $2, $3)
+        ON CONFLICT DO NOTHING"#,
+        jobid,
+        day,
+        viewer
+    )
+    .execute(pool)
+    .await?;
+    if seen.rows_affected() == 1 {
+        record(pool, &[jobid], Metric::View).await?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct JobViewRequest {
+    jobid: i32,
+}
+impl JobViewRequest {
+    /// The job, counting a view in the background unless the owner is looking at it.
+    /// Anonymous viewers are told apart by a keyed hash of their address
+    pub async fn execute(
+        &self,
+        pool: &Pool<Postgres>,
+        viewer: Option<Uuid>,
+        client: &Client,
+    ) -> Result<Job, Error> {
+        let job = get_job(pool, self.jobid).await?.ok_or(Error::Forbidden)?;
+        let day = today();
+        let key = match (viewer, &client.ip) {
+            (Some(viewer), _) if viewer == job.owner => return Ok(job),
+            (Some(viewer), _) => viewer.to_string(),
+            (None, Some(ip)) => viewer_hash(ip, day),
+            (None, None) => return Ok(job),
+        };
+        let pool = pool.clone();
+        let jobid = self.jobid;
+        tokio::spawn(async move {
+            if let Err(e) = count_view(&pool, jobid, day, &key).await {
+                println!("Job stats error: {}", e);
+            }
+        });
+        Ok(job)
+    }
+}
+
+/// Drops the viewers of past days, they are only needed to count views once a day
+pub async fn prune_task(pool: Pool<Postgres>) {
+    let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+    loop {
+        interval.tick().await;
+        let pruned = sqlx::query!("DELETE FROM job_views_seen WHERE day < $1", today())
+            .execute(&pool)
+            .await;
+        if let Err(e) = pruned {
+            println!("View pruning error: {}", e);
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct DailyStats {
+    #[serde(with = "iso_date")]
+    day: Date,
+    impressions: i64,
+    views: i64,
+    saves: i64,
+    applications: i64,
+}
+
+/// Totals of the range. Views are a share of impressions, saves and applications of views
+/// and hires of applications
+#[derive(Debug, Serialize)]
+pub struct Funnel {
+    impressions: i64,
+    views: i64,
+    saves: i64,
+    applications: i64,
+    /// Hired applicants, counted on the day of hiring
+    hires: i64,
+    view_rate: Option<f64>,
+    save_rate: Option<f64>,
+    apply_rate: Option<f64>,
+    hire_rate: Option<f64>,
+}
+
+fn rate(part: i64, whole: i64) -> Option<f64> {
+    (whole > 0).then(|| part as f64 / whole as f64)
+}
+
+#[derive(Debug, Serialize)]
+pub struct JobTotals {
+    jobid: i32,
+    job_location: String,
+    impressions: i64,
+    views: i64,
+    saves: i64,
+    applications: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Analytics {
+    /// Every day of the range, oldest first
+    daily: Vec<DailyStats>,
+    funnel: Funnel,
+    /// Only for the whole company, most viewed first
+    #[serde(skip_serializing_if = "Option::is_none")]
+    jobs: Option<Vec<JobTotals>>,
+}
+
+/// Checks the requested range, the last `DEFAULT_RANGE` up to today by default
+fn date_range(from: Option<Date>, to: Option<Date>) -> Result<(Date, Date), Error> {
+    let to = to.unwrap_or_else(today);
+    let from = from.unwrap_or(to - DEFAULT_RANGE);
+    if from > to || to - from > MAX_RANGE {
+        return Err(Error::ImproperRange);
+    }
+    Ok((from, to))
+}
+
+/// The series and funnel of the jobs of `owner`, or of one of them
+async fn get_analytics(
+    pool: &Pool<Postgres>,
+    owner: Uuid,
+    jobid: Option<i32>,
+    from: Date,
+    to: Date,
+) -> Result<Analytics, sqlx::Error> {
+    let daily = sqlx::query_as!(
+        DailyStats,
+        r#"SELECT
+            days.day::date "day!",
+            coalesce(sum(stats.impressions), 0)::int8 "impressions!",
+            coalesce(sum(stats.views), 0)::int8 "views!",
+            coalesce(sum(stats.saves), 0)::int8 "saves!",
+            coalesce(sum(stats.applications), 0)::int8 "applications!"
+        FROM generate_series($2::date, $3::date, interval '1 day') days(day)
+        LEFT JOIN job_stats_daily stats ON stats.day = days.day::date AND
+            stats.jobid IN (SELECT jobid FROM jobs WHERE owner = $1) AND
+            ($4::int4 IS NULL OR stats.jobid = $4)
+        GROUP BY days.day
+        ORDER BY days.day"#,
+        owner,
+        from,
+        to,
+        jobid
+    )
+    .fetch_all(pool)
+    .await?;
+    let hires = sqlx::query_scalar!(
+        r#"SELECT count(*) "count!"
+        FROM applications
+        JOIN jobs ON jobs.jobid = applications.jobid
+        WHERE jobs.owner = $1 AND
+            ($4::int4 IS NULL OR jobs.jobid = $4) AND
+            (applications.hired_at AT TIME ZONE 'UTC')::date BETWEEN $2 AND $3"#,
+        owner,
+        from,
+        to,
+        jobid
+    )
+    .fetch_one(pool)
+    .await?;
+    let total = |f: fn(&DailyStats) -> i64| daily.iter().map(f).sum::<i64>();
+    let (impressions, views, saves, applications) = (
+        total(|d| d.impressions),
+        total(|d| d.views),
+        total(|d| d.saves),
+        total(|d| d.applications),
+    );
+    let funnel = Funnel {
+        impressions,
+        views,
+        saves,
+        applications,
+        hires,
+        view_rate: rate(views, impressions),
+        save_rate: rate(saves, views),
+        apply_rate: rate(applications, views),
+        hire_rate: rate(hires, applications),
+    };
+    Ok(Analytics {
+        daily,
+        funnel,
+        jobs: None,
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct JobAnalyticsRequest {
+    jobid: i32,
+    #[serde(default, with = "iso_date::option")]
+    from: Option<Date>,
+    #[serde(default, with = "iso_date::option")]
+    to: Option<Date>,
+}
+impl JobAnalyticsRequest {
+    pub async fn execute(&self, pool: &Pool<Postgres>, owner: Uuid) -> Result<Analytics, Error> {
+        let (from, to) = date_range(self.from, self.to)?;
+        match get_job(pool, self.jobid).await? {
+            Some(job) if job.owner == owner => {}
+            _ => return Err(Error::Forbidden),
+        }
+        Ok(get_analytics(pool, owner, Some(self.jobid), from, to).await?)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CompanyAnalyticsRequest {
+    #[serde(default, with = "iso_date::option")]
+    from: Option<Date>,
+    #[serde(default, with = "iso_date::option")]
+    to: Option<Date>,
+}
+impl CompanyAnalyticsRequest {
+    /// All jobs of the company, with the totals of each job
+    pub async fn execute(&self, pool: &Pool<Postgres>, owner: Uuid) -> Result<Analytics, Error> {
+        let (from, to) = date_range(self.from, self.to)?;
+        let mut analytics = get_analytics(pool, owner, None, from, to).await?;
+        let jobs = sqlx::query_as!(
+            JobTotals,
+            r#"SELECT
+                jobs.jobid,
+                jobs.job_location,
+                coalesce(sum(stats.impressions), 0)::int8 "impressions!",
+                coalesce(sum(stats.views), 0)::int8 "views!",
+                coalesce(sum(stats.saves), 0)::int8 "saves!",
+                coalesce(sum(stats.applications), 0)::int8 "applications!"
+            FROM jobs
+            LEFT JOIN job_stats_daily stats ON stats.jobid = jobs.jobid AND
+                stats.day BETWEEN $2 AND $3
+            WHERE jobs.owner = $1
+            GROUP BY jobs.jobid
+            ORDER BY 4 DESC, jobs.jobid"#,
+            owner,
+            from,
+            to
+        )
+        .fetch_all(pool)
+        .await?;
+        analytics.jobs = Some(jobs);
+        Ok(analytics)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::date;
+
+    #[test]
+    fn viewer_hashes_change_daily() {
+        let day = date!(2026 - 10 - 19);
+        assert_eq!(viewer_hash("1.2.3.4", day), viewer_hash("1.2.3.4", day));
+        assert_ne!(viewer_hash("1.2.3.4", day), viewer_hash("1.2.3.5", day));
+        assert_ne!(
+            viewer_hash("1.2.3.4", day),
+            viewer_hash("1.2.3.4", day.next_day().unwrap())
+        );
+    }
+}
@@ -0,0 +1,193 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+use crate::analytics::{spawn_record, Metric};
+use crate::error::Error;
+use crate::notifications::{notify, Event};
+use crate::users::UserRole;
+
+#[derive(Debug, Deserialize)]
+pub struct ApplyRequest {
+    jobid: i32,
+}
+impl ApplyRequest {
+    pub async fn execute(&self, pool: &Pool<Postgres>, userid: Uuid) -> Result<Application, Error> {
+        let open = sqlx::query_scalar!(
+            r#"SELECT archived_at IS NULL AND expires_at > now() "open!" FROM jobs WHERE jobid = $1"#,
+            self.jobid
+        )
+        .fetch_optional(pool)
+        .await?;
+        if open == Some(false) {
+            return Err(Error::JobArchived);
+        }
+        let application = sqlx::query_as!(
+            Application,
+            r#"INSERT INTO applications (jobid, userid, creation_time)
+            VALUES ($1, $2, $3)
+            RETURNING applicationid, jobid, userid, creation_time, hired_at, completed_at"#,
+            self.jobid,
+            userid,
+            time::OffsetDateTime::now_utc()
+        )
+        .fetch_one(pool)
+        .await?;
+        spawn_record(pool, vec![application.jobid], Metric::Application);
+
+        let (_, owner) = Application::get_parties(pool, application.applicationid).await?;
+        notify(
+            pool,
+            owner,
+            Event::NewApplication {
+                applicationid: application.applicationid,
+                jobid: application.jobid,
+            },
+        )
+        .await;
+        Ok(application)
+    }
+}
+
+#[derive(Debug, Clone, sqlx::FromRow, Serialize, Deserialize)]
+pub struct Application {
+    pub(crate) applicationid: i32,
+    pub(crate) jobid: i32,
+    pub(crate) userid: Uuid,
+    pub(crate) creation_time: time::OffsetDateTime,
+    /// Set when the company hires the applicant, see `contracts`
+    pub(crate) hired_at: Option<time::OffsetDateTime>,
+    /// Set by either side once the engagement ends, reviews can be written from then on
+    pub(crate) completed_at: Option<time::OffsetDateTime>,
+}
+
+impl Application {
+    /// The applicant and the company owning the job
+    pub async fn get_parties(
+        pool: &Pool<Postgres>,
+        applicationid: i32,
+    ) -> Result<(Uuid, Uuid), sqlx::Error> {
+        let parties = sqlx::query!(
+            r#"SELECT applications.userid, jobs.owner
+            FROM applications
+            JOIN jobs ON jobs.jobid = applications.jobid
+            WHERE applications.applicationid = $1
+            "#,
+            applicationid
+        )
+        .fetch_one(pool)
+        .await?;
+        Ok((parties.userid, parties.owner))
+    }
+    /// Whether the user is a side of the application - the applicant or the owner of the job
+    pub async fn is_participant(
+        pool: &Pool<Postgres>,
+        applicationid: i32,
+        userid: Uuid,
+    ) -> Result<bool, sqlx::Error> {
+        let found = sqlx::query!(
+            r#"SELECT applications.applicationid
+            FROM applications
+            JOIN jobs ON jobs.jobid = applications.jobid
+            WHERE applications.applicationid = $1 AND
+                (applications.userid = $2 OR jobs.owner = $2)
+            "#,
+            applicationid,
+            userid
+        )
+        .fetch_optional(pool)
+        .await?;
+        Ok(found.is_some())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CompleteRequest {
+    applicationid: i32,
+}
+impl CompleteRequest {
+    /// Ends the engagement of a hired applicant, either side can do it
+    pub async fn execute(&self, pool: &Pool<Postgres>, userid: Uuid) -> Result<(), Error> {
+        let engagement = sqlx::query!(
+            r#"SELECT applications.userid, applications.hired_at, jobs.owner
+            FROM applications
+            JOIN jobs ON jobs.jobid = applications.jobid
+            WHERE applications.applicationid = $1"#,
+            self.applicationid
+        )
+        .fetch_optional(pool)
+        .await?
+        .filter(|e| e.userid == userid || e.owner == userid)
+        .ok_or(Error::Forbidden)?;
+        if engagement.hired_at.is_none() {
+            return Err(Error::NotHired);
+        }
+        let completed = sqlx::query!(
+            "UPDATE applications SET completed_at = $2 WHERE applicationid = $1 AND completed_at IS NULL",
+            self.applicationid,
+            time::OffsetDateTime::now_utc()
+        )
+        .execute(pool)
+        .await?;
+        if completed.rows_affected() == 0 {
+            return Ok(());
+        }
+
+        let other = if engagement.userid == userid {
+            engagement.owner
+        } else {
+            engagement.userid
+        };
+        notify(
+            pool,
+            other,
+            Event::EngagementCompleted {
+                applicationid: self.applicationid,
+            },
+        )
+        .await;
+        Ok(())
+    }
+}
+
+/// Applications sent by a user, or received by a company for any of its jobs
+pub async fn get_applications(
+    pool: &Pool<Postgres>,
+    userid: Uuid,
+    role: &UserRole,
+) -> Result<Vec<Application>, sqlx::Error> {
+    match role {
+        UserRole::User => {
+            sqlx::query_as!(
+                Application,
+                r#"SELECT applicationid, jobid, userid, creation_time, hired_at, completed_at
+                FROM applications
+                WHERE userid = $1
+                ORDER BY creation_time DESC"#,
+                userid
+            )
+            .fetch_all(pool)
+            .await
+        }
+        UserRole::Company => {
+            sqlx::query_as!(
+                Application,
+                r#"SELECT
+                    applications.applicationid,
+                    applications.jobid,
+                    applications.userid,
+                    applications.creation_time,
+                    applications.hired_at,
+                    applications.completed_at
+                FROM applications
+                JOIN jobs ON jobs.jobid = applications.jobid
+                WHERE jobs.owner = $1
+                ORDER BY applications.creation_time DESC"#,
+                userid
+            )
+            .fetch_all(pool)
+            .await
+        }
+        UserRole::Admin => Ok(Vec::new()),
+    }
+}
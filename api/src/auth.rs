@@ -8,7 +8,6 @@ use warp::reject::Reject;
 use crate::error::{self, Error, WebResult};
 use crate::users::UserRole;
 
-use dotenv;
 use lazy_static::lazy_static;
 
 lazy_static! {
@@ -61,7 +60,7 @@ pub fn decode_header(headers: HeaderMap<HeaderValue>) -> error::Result<Claim> {
     }
 }
 pub async fn async_decode(headers: HeaderMap<HeaderValue>) -> WebResult<Claim> {
-    decode_header(headers).map_err(|err| warp::reject::custom(err))
+    decode_header(headers).map_err(warp::reject::custom)
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -77,6 +76,7 @@ impl Claim {
     pub fn get_role(&self) -> UserRole {
         match &self.role.to_lowercase()[..] {
             "company" => UserRole::Company,
+            "admin" => UserRole::Admin,
             _ => UserRole::User,
         }
     }
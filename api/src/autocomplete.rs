@@ -0,0 +1,156 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+
+use crate::locations::{fold, LOCALITIES};
+use crate::tags::slugify;
+
+/// Lower than the pg_trgm default of 0.3, so that swapped letters ("kelenr") still match
+const SIMILARITY_THRESHOLD: &str = "0.2";
+const DEFAULT_LIMIT: i64 = 10;
+const MAX_LIMIT: i64 = 50;
+
+/// LIKE pattern matching text that starts with `prefix`, taken literally
+fn like_prefix(prefix: &str) -> String {
+    let mut pattern = String::with_capacity(prefix.len() + 1);
+    for c in prefix.chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AutocompleteRequest {
+    text: String,
+    limit: Option<i64>,
+}
+impl AutocompleteRequest {
+    fn get_limit(&self) -> i64 {
+        self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+    }
+    /// Canonical tags whose slug or a synonym starts with the text first, then similar ones,
+    /// each ranked by how many jobs use them
+    pub async fn get_tags(&self, pool: &Pool<Postgres>) -> Result<Vec<TagSuggestion>, sqlx::Error> {
+        let slug = slugify(&self.text);
+        let mut tx = pool.begin().await?;
+        sqlx::query!(
+            "SELECT set_config('pg_trgm.similarity_threshold', $1, true)",
+            SIMILARITY_THRESHOLD
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        let tags = sqlx::query_as!(
+            TagSuggestion,
+            r#"SELECT
+                tags.name "tag",
+                tags.category,
+                count(job_tags.jobid) "uses!"
+            FROM tags
+            LEFT JOIN job_tags ON job_tags.tagid = tags.tagid
+            WHERE tags.slug LIKE $3 OR tags.slug % $1 OR tags.tagid IN (
+                SELECT tagid FROM tag_synonyms
+                WHERE synonym LIKE $3 OR synonym % $1
+            )
+            GROUP BY tags.tagid
+            ORDER BY
+                tags.slug LIKE $3 DESC,
+                count(job_tags.jobid) DESC,
+                similarity(tags.slug, $1) DESC
+            LIMIT $2"#,
+            slug,
+            self.get_limit(),
+            like_prefix(&slug)
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(tags)
+    }
+    /// Localities from the gazetteer, ranked like tags by the number of jobs in them
+    pub async fn get_locations(
+        &self,
+        pool: &Pool<Postgres>,
+    ) -> Result<Vec<LocationSuggestion>, sqlx::Error> {
+        let folded = fold(&self.text);
+        let mut tx = pool.begin().await?;
+        sqlx::query!(
+            "SELECT set_config('pg_trgm.similarity_threshold', $1, true)",
+            SIMILARITY_THRESHOLD
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        let locations = sqlx::query_as!(
+            LocationSuggestion,
+            r#"SELECT
+                localities.name,
+                localities.voivodeship,
+                count(jobs.jobid) "uses!"
+            FROM localities
+            LEFT JOIN jobs ON jobs.city = localities.name
+            WHERE localities.folded LIKE $3 OR localities.folded % $1
+            GROUP BY localities.name, localities.voivodeship, localities.folded
+            ORDER BY
+                localities.folded LIKE $3 DESC,
+                count(jobs.jobid) DESC,
+                similarity(localities.folded, $1) DESC
+            LIMIT $2"#,
+            folded,
+            self.get_limit(),
+            like_prefix(&folded)
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(locations)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct TagSuggestion {
+    tag: String,
+    category: Option<String>,
+    uses: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LocationSuggestion {
+    name: String,
+    voivodeship: String,
+    uses: i64,
+}
+
+/// Copies the bundled gazetteer into the database, so it can be searched with trigram indexes
+pub async fn seed_localities(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    for l in LOCALITIES.iter() {
+        sqlx::query!(
+            r#"INSERT INTO localities (name, voivodeship, folded, latitude, longitude)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (name, voivodeship) DO UPDATE SET
+                folded = $3,
+                latitude = $4,
+                longitude = $5"#,
+            l.name,
+            l.voivodeship,
+            fold(&l.name),
+            l.latitude,
+            l.longitude
+        )
+        .execute(pool)
+        .await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards_are_escaped() {
+        assert_eq!(like_prefix("kel"), "kel%");
+        assert_eq!(like_prefix("50%_a\\"), "50\\%\\_a\\\\%");
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+use crate::analytics::{spawn_record, Metric};
+use crate::jobs::{ContractType, Job, JobHours, JobMode, PayUnit};
+
+#[derive(Debug, Deserialize)]
+pub struct BookmarkRequest {
+    jobid: i32,
+}
+impl BookmarkRequest {
+    pub async fn save(&self, pool: &Pool<Postgres>, userid: Uuid) -> Result<(), sqlx::Error> {
+        let saved = sqlx::query!(
+            r#"INSERT INTO saved_jobs (userid, jobid, saved_at)
+            VALUES ($1, $2, $3)
+            ON CONFLICT DO NOTHING"#,
+            userid,
+            self.jobid,
+            time::OffsetDateTime::now_utc()
+        )
+        .execute(pool)
+        .await?;
+        if saved.rows_affected() == 1 {
+            spawn_record(pool, vec![self.jobid], Metric::Save);
+        }
+        Ok(())
+    }
+    pub async fn remove(&self, pool: &Pool<Postgres>, userid: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "DELETE FROM saved_jobs WHERE userid = $1 AND jobid = $2",
+            userid,
+            self.jobid
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SavedJob {
+    #[serde(flatten)]
+    job: Job,
+    saved_at: time::OffsetDateTime,
+}
+
+pub async fn get_saved_jobs(
+    pool: &Pool<Postgres>,
+    userid: Uuid,
+) -> Result<Vec<SavedJob>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"SELECT
+            jobs.jobid,
+            jobs.owner,
+            jobs.creation_time,
+            jobs.job_location,
+            jobs.contract_type "contract_type: ContractType",
+            jobs.mode "mode: JobMode",
+            jobs.hours "hours: JobHours",
+            jobs.description,
+            jobs.tags,
+            jobs.pay_amount,
+            jobs.pay_unit "pay_unit: PayUnit",
+            jobs.pay_currency,
+            jobs.pay_gross,
+            jobs.city,
+            jobs.voivodeship,
+            jobs.postal_code,
+            jobs.latitude,
+            jobs.longitude,
+            jobs.expires_at,
+            jobs.archived_at,
+            saved_jobs.saved_at
+        FROM saved_jobs
+        JOIN jobs ON jobs.jobid = saved_jobs.jobid
+        WHERE saved_jobs.userid = $1
+        ORDER BY saved_jobs.saved_at DESC"#,
+        userid
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|r| SavedJob {
+            job: Job {
+                jobid: r.jobid,
+                owner: r.owner,
+                creation_time: r.creation_time,
+                job_location: Some(r.job_location),
+                contract_type: r.contract_type,
+                mode: r.mode,
+                hours: r.hours,
+                description: r.description,
+                tags: r.tags,
+                pay_amount: r.pay_amount,
+                pay_unit: r.pay_unit,
+                pay_currency: r.pay_currency,
+                pay_gross: r.pay_gross,
+                city: r.city,
+                voivodeship: r.voivodeship,
+                postal_code: r.postal_code,
+                latitude: r.latitude,
+                longitude: r.longitude,
+                expires_at: r.expires_at,
+                archived_at: r.archived_at,
+            },
+            saved_at: r.saved_at,
+        })
+        .collect())
+}
+
+/// Which of the given jobs the user has saved
+pub async fn get_saved_ids(
+    pool: &Pool<Postgres>,
+    userid: Uuid,
+    jobids: &[i32],
+) -> Result<HashSet<i32>, sqlx::Error> {
+    let rows = sqlx::query!(
+        "SELECT jobid FROM saved_jobs WHERE userid = $1 AND jobid = ANY($2)",
+        userid,
+        jobids
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(|r| r.jobid).collect())
+}
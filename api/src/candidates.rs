@@ -0,0 +1,386 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::companies::is_verified;
+use crate::error::Error;
+use crate::jobs::{get_job, JobHours, JobMode};
+use crate::locations::find_locality;
+use crate::notifications::{notify, Event};
+use crate::reviews::{rating_summaries, RatingSummary};
+use crate::tags::{canonical_names, validate_tags};
+use crate::users::{age_on, ADULT_AGE};
+
+const MAX_TRAVEL_KM: f64 = 200.0;
+/// Commuting distance assumed when the profile has a city but no `within_km`
+pub(crate) const DEFAULT_TRAVEL_KM: f64 = 20.0;
+const FOUND_CANDIDATES: i64 = 50;
+
+#[derive(Debug, Serialize)]
+pub struct CandidateProfile {
+    /// Canonical tag names
+    pub(crate) skills: Vec<String>,
+    /// When the candidate can work
+    pub(crate) hours: Vec<JobHours>,
+    pub(crate) modes: Vec<JobMode>,
+    pub(crate) city: Option<String>,
+    /// How far from `city` the candidate can commute
+    pub(crate) within_km: Option<f64>,
+    /// Verified companies can find the candidate and invite them to apply
+    pub(crate) in_talent_pool: bool,
+    /// Companies only see the initial of the surname
+    pub(crate) hide_surname: bool,
+    /// Without the city companies can't find the candidate by location
+    pub(crate) show_city: bool,
+}
+impl Default for CandidateProfile {
+    fn default() -> Self {
+        CandidateProfile {
+            skills: Vec::new(),
+            hours: Vec::new(),
+            modes: Vec::new(),
+            city: None,
+            within_km: None,
+            in_talent_pool: false,
+            hide_surname: true,
+            show_city: true,
+        }
+    }
+}
+
+/// The profile of the candidate, empty until they fill it in
+pub async fn get_candidate_profile(
+    pool: &Pool<Postgres>,
+    userid: Uuid,
+) -> Result<CandidateProfile, sqlx::Error> {
+    let profile = sqlx::query_as!(
+        CandidateProfile,
+        r#"SELECT
+            skills,
+            hours "hours: Vec<JobHours>",
+            modes "modes: Vec<JobMode>",
+            city,
+            within_km,
+            in_talent_pool,
+            hide_surname,
+            show_city
+        FROM candidate_profiles
+        WHERE userid = $1"#,
+        userid
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(profile.unwrap_or_default())
+}
+
+/// The items in their first order, without repetitions
+fn unique<T: PartialEq + Clone>(items: &[T]) -> Vec<T> {
+    let mut unique: Vec<T> = Vec::new();
+    for item in items {
+        if !unique.contains(item) {
+            unique.push(item.clone());
+        }
+    }
+    unique
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateCandidateProfileRequest {
+    #[serde(default)]
+    skills: Vec<String>,
+    #[serde(default)]
+    hours: Vec<JobHours>,
+    #[serde(default)]
+    modes: Vec<JobMode>,
+    /// Name of a locality, unknown names are rejected
+    #[serde(default)]
+    city: Option<String>,
+    #[serde(default)]
+    within_km: Option<f64>,
+    #[serde(default)]
+    in_talent_pool: bool,
+    #[serde(default = "default_true")]
+    hide_surname: bool,
+    #[serde(default = "default_true")]
+    show_city: bool,
+}
+impl UpdateCandidateProfileRequest {
+    /// Replaces the profile, skills are stored as their canonical tags
+    pub async fn execute(
+        &self,
+        pool: &Pool<Postgres>,
+        userid: Uuid,
+    ) -> Result<CandidateProfile, Error> {
+        validate_tags(&self.skills)?;
+        if self
+            .within_km
+            .is_some_and(|km| !(km > 0.0 && km <= MAX_TRAVEL_KM))
+        {
+            return Err(Error::ImproperProfile);
+        }
+        let locality = match self.city.as_deref().map(str::trim) {
+            None | Some("") => None,
+            Some(name) => Some(find_locality(name).ok_or(Error::UnknownLocation)?),
+        };
+        let city = locality.map(|l| l.name.to_owned());
+        let skills = unique(&canonical_names(pool, &self.skills).await?);
+        let hours = unique(&self.hours);
+        let modes = unique(&self.modes);
+        sqlx::query!(
+            r#"INSERT INTO candidate_profiles
+                (userid, skills, hours, modes, city, within_km, in_talent_pool, hide_surname, show_city, updated_at,
+                latitude, longitude)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+            ON CONFLICT (userid) DO UPDATE SET
+                skills = excluded.skills,
+                hours = excluded.hours,
+                modes = excluded.modes,
+                city = excluded.city,
+                within_km = excluded.within_km,
+                in_talent_pool = excluded.in_talent_pool,
+                hide_surname = excluded.hide_surname,
+                show_city = excluded.show_city,
+                updated_at = excluded.updated_at,
+                latitude = excluded.latitude,
+                longitude = excluded.longitude"#,
+            userid,
+            &skills,
+            &hours as &[JobHours],
+            &modes as &[JobMode],
+            city,
+            self.within_km,
+            self.in_talent_pool,
+            self.hide_surname,
+            self.show_city,
+            OffsetDateTime::now_utc(),
+            locality.map(|l| l.latitude),
+            locality.map(|l| l.longitude)
+        )
+        .execute(pool)
+        .await?;
+        Ok(CandidateProfile {
+            skills,
+            hours,
+            modes,
+            city,
+            within_km: self.within_km,
+            in_talent_pool: self.in_talent_pool,
+            hide_surname: self.hide_surname,
+            show_city: self.show_city,
+        })
+    }
+}
+
+/// Locates the cities of profiles saved before their coordinates were stored
+pub async fn backfill_profile_coordinates(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    let profiles = sqlx::query!(
+        r#"SELECT userid, city "city!"
+        FROM candidate_profiles
+        WHERE city IS NOT NULL AND latitude IS NULL"#
+    )
+    .fetch_all(pool)
+    .await?;
+    for profile in profiles {
+        if let Some(locality) = find_locality(&profile.city) {
+            sqlx::query!(
+                "UPDATE candidate_profiles SET latitude = $2, longitude = $3 WHERE userid = $1",
+                profile.userid,
+                locality.latitude,
+                locality.longitude
+            )
+            .execute(pool)
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+/// "Jan K." for "Jan Kowalski"
+fn display_name(full_name: &str, hide_surname: bool) -> String {
+    let words: Vec<&str> = full_name.split_whitespace().collect();
+    match words.as_slice() {
+        [first, .., last] if hide_surname => {
+            let initial: String = last.chars().take(1).collect();
+            format!("{} {}.", first, initial)
+        }
+        _ => words.join(" "),
+    }
+}
+
+/// What companies see of a candidate in the talent pool, contact details are never shown
+#[derive(Debug, Serialize)]
+pub struct TalentCard {
+    userid: Uuid,
+    name: String,
+    /// Candidates without a birth date are counted as minors
+    minor: bool,
+    city: Option<String>,
+    /// From the `near` of the search
+    #[serde(skip_serializing_if = "Option::is_none")]
+    distance_km: Option<f64>,
+    skills: Vec<String>,
+    hours: Vec<JobHours>,
+    modes: Vec<JobMode>,
+    rating: RatingSummary,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TalentSearchRequest {
+    /// Candidates need at least one of them, unless empty
+    #[serde(default)]
+    skills: Vec<String>,
+    /// Candidates need to be available at some of them, unless empty
+    #[serde(default)]
+    hours: Vec<JobHours>,
+    /// Name of a locality, only candidates who can commute to it are returned
+    #[serde(default)]
+    near: Option<String>,
+}
+impl TalentSearchRequest {
+    /// Candidates of the talent pool, those with the most of the skills first
+    pub async fn execute(
+        &self,
+        pool: &Pool<Postgres>,
+        company: Uuid,
+    ) -> Result<Vec<TalentCard>, Error> {
+        if !is_verified(pool, company).await? {
+            return Err(Error::NotVerified);
+        }
+        validate_tags(&self.skills)?;
+        let near = match &self.near {
+            Some(name) => {
+                let locality = find_locality(name).ok_or(Error::UnknownLocation)?;
+                Some((locality.latitude, locality.longitude))
+            }
+            None => None,
+        };
+        let skills = canonical_names(pool, &self.skills).await?;
+        let candidates = sqlx::query!(
+            r#"SELECT
+                users.userid,
+                users.full_name,
+                users.birth_date,
+                candidate_profiles.skills,
+                candidate_profiles.hours "hours: Vec<JobHours>",
+                candidate_profiles.modes "modes: Vec<JobMode>",
+                candidate_profiles.city,
+                candidate_profiles.show_city,
+                candidate_profiles.hide_surname,
+                travel.distance_km
+            FROM candidate_profiles
+            JOIN users ON users.userid = candidate_profiles.userid
+            CROSS JOIN LATERAL (
+                SELECT 2 * 6371 * asin(sqrt(
+                    power(sin(radians(candidate_profiles.latitude - $3::float8) / 2), 2) +
+                    cos(radians($3::float8)) * cos(radians(candidate_profiles.latitude)) *
+                    power(sin(radians(candidate_profiles.longitude - $4::float8) / 2), 2)
+                )) distance_km
+            ) travel
+            WHERE candidate_profiles.in_talent_pool AND
+                (cardinality($1::text[]) = 0 OR candidate_profiles.skills && $1) AND
+                (cardinality($2::job_hours[]) = 0 OR candidate_profiles.hours && $2) AND
+                ($3::float8 IS NULL OR candidate_profiles.show_city AND
+                    travel.distance_km <= coalesce(candidate_profiles.within_km, $5))
+            ORDER BY
+                cardinality(ARRAY(SELECT unnest(candidate_profiles.skills) INTERSECT SELECT unnest($1))) DESC,
+                candidate_profiles.updated_at DESC
+            LIMIT $6"#,
+            &skills,
+            &self.hours as &[JobHours],
+            near.map(|(lat, _)| lat),
+            near.map(|(_, lon)| lon),
+            DEFAULT_TRAVEL_KM,
+            FOUND_CANDIDATES
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let today = OffsetDateTime::now_utc().date();
+        let mut found: Vec<TalentCard> = candidates
+            .into_iter()
+            .map(|c| TalentCard {
+                userid: c.userid,
+                name: display_name(&c.full_name, c.hide_surname),
+                minor: c.birth_date.is_none_or(|b| age_on(b, today) < ADULT_AGE),
+                city: c.city.filter(|_| c.show_city),
+                distance_km: c.distance_km.map(|d| (d * 10.0).round() / 10.0),
+                skills: c.skills,
+                hours: c.hours,
+                modes: c.modes,
+                rating: RatingSummary::default(),
+            })
+            .collect();
+        let userids: Vec<Uuid> = found.iter().map(|c| c.userid).collect();
+        let mut ratings = rating_summaries(pool, &userids).await?;
+        for card in &mut found {
+            card.rating = ratings.remove(&card.userid).unwrap_or_default();
+        }
+        Ok(found)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InviteRequest {
+    /// The candidate
+    userid: Uuid,
+    jobid: i32,
+}
+impl InviteRequest {
+    /// Notifies a candidate of the talent pool about an open job, once per job
+    pub async fn execute(&self, pool: &Pool<Postgres>, company: Uuid) -> Result<(), Error> {
+        if !is_verified(pool, company).await? {
+            return Err(Error::NotVerified);
+        }
+        let job = get_job(pool, self.jobid)
+            .await?
+            .filter(|j| j.owner == company)
+            .ok_or(Error::Forbidden)?;
+        if job.archived_at.is_some() || job.expires_at <= OffsetDateTime::now_utc() {
+            return Err(Error::JobArchived);
+        }
+        let in_pool = sqlx::query_scalar!(
+            "SELECT in_talent_pool FROM candidate_profiles WHERE userid = $1",
+            self.userid
+        )
+        .fetch_optional(pool)
+        .await?;
+        if in_pool != Some(true) {
+            return Err(Error::Forbidden);
+        }
+        let applied = sqlx::query_scalar!(
+            "SELECT applicationid FROM applications WHERE jobid = $1 AND userid = $2",
+            self.jobid,
+            self.userid
+        )
+        .fetch_optional(pool)
+        .await?;
+        if applied.is_some() {
+            return Err(Error::AlreadyInvited);
+        }
+        let invited = sqlx::query!(
+            r#"INSERT INTO talent_invitations (jobid, userid, creation_time)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (jobid, userid) DO NOTHING"#,
+            self.jobid,
+            self.userid,
+            OffsetDateTime::now_utc()
+        )
+        .execute(pool)
+        .await?;
+        if invited.rows_affected() == 0 {
+            return Err(Error::AlreadyInvited);
+        }
+        notify(
+            pool,
+            self.userid,
+            Event::InvitedToApply { jobid: self.jobid },
+        )
+        .await;
+        Ok(())
+    }
+}
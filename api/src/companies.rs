@@ -0,0 +1,208 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::error::Error;
+use crate::jobs::{ContractType, Job, JobHours, JobMode, PayUnit};
+use crate::reviews::{rating_summaries, RatingSummary};
+
+const MAX_DESCRIPTION_LENGTH: usize = 5000;
+const MAX_FIELD_LENGTH: usize = 255;
+
+/// What job listings show about the company behind them
+#[derive(Debug, Serialize, Clone)]
+pub struct CompanySummary {
+    pub(crate) userid: Uuid,
+    company_name: String,
+    logo_url: Option<String>,
+    /// Checked by an admin
+    verified: bool,
+    rating: RatingSummary,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CompanyProfile {
+    #[serde(flatten)]
+    summary: CompanySummary,
+    description: Option<String>,
+    website: Option<String>,
+    location: Option<String>,
+    open_jobs: Vec<Job>,
+}
+
+/// Summaries of the given companies, unknown ids are left out
+pub async fn get_company_summaries(
+    pool: &Pool<Postgres>,
+    userids: &[Uuid],
+) -> Result<Vec<CompanySummary>, sqlx::Error> {
+    let companies = sqlx::query!(
+        r#"SELECT
+            companies.userid,
+            companies.company_name,
+            companies.logo_url,
+            companies.verified_at IS NOT NULL "verified!"
+        FROM companies
+        WHERE companies.userid = ANY($1)"#,
+        userids
+    )
+    .fetch_all(pool)
+    .await?;
+    let mut ratings = rating_summaries(pool, userids).await?;
+    Ok(companies
+        .into_iter()
+        .map(|c| CompanySummary {
+            userid: c.userid,
+            company_name: c.company_name,
+            logo_url: c.logo_url,
+            verified: c.verified,
+            rating: ratings.remove(&c.userid).unwrap_or_default(),
+        })
+        .collect())
+}
+
+/// Whether an admin checked the company
+pub async fn is_verified(pool: &Pool<Postgres>, userid: Uuid) -> Result<bool, sqlx::Error> {
+    let verified_at = sqlx::query_scalar!(
+        "SELECT verified_at FROM companies WHERE userid = $1",
+        userid
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(verified_at.flatten().is_some())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CompanyProfileRequest {
+    userid: Uuid,
+}
+impl CompanyProfileRequest {
+    pub async fn execute(&self, pool: &Pool<Postgres>) -> Result<CompanyProfile, Error> {
+        let summary = get_company_summaries(pool, &[self.userid])
+            .await?
+            .pop()
+            .ok_or(Error::Forbidden)?;
+        let details = sqlx::query!(
+            "SELECT description, website, location FROM companies WHERE userid = $1",
+            self.userid
+        )
+        .fetch_one(pool)
+        .await?;
+        let open_jobs = sqlx::query_as!(
+            Job,
+            r#"SELECT
+                jobid,
+                owner,
+                creation_time,
+                job_location,
+                contract_type "contract_type: ContractType",
+                mode "mode: JobMode",
+                hours "hours: JobHours",
+                description,
+                tags,
+                pay_amount,
+                pay_unit "pay_unit: PayUnit",
+                pay_currency,
+                pay_gross,
+                city,
+                voivodeship,
+                postal_code,
+                latitude,
+                longitude,
+                expires_at,
+                archived_at
+            FROM jobs
+            WHERE owner = $1 AND archived_at IS NULL AND expires_at > now()
+            ORDER BY creation_time DESC"#,
+            self.userid
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(CompanyProfile {
+            summary,
+            description: details.description,
+            website: details.website,
+            location: details.location,
+            open_jobs,
+        })
+    }
+}
+
+fn valid_url(url: &str) -> bool {
+    (url.starts_with("https://") || url.starts_with("http://"))
+        && url.len() <= MAX_FIELD_LENGTH
+        && !url.contains(char::is_whitespace)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateProfileRequest {
+    description: Option<String>,
+    website: Option<String>,
+    /// Link to an image hosted by the company
+    logo_url: Option<String>,
+    location: Option<String>,
+}
+impl UpdateProfileRequest {
+    /// Replaces the editable part of the profile, missing fields are cleared
+    pub async fn execute(&self, pool: &Pool<Postgres>, userid: Uuid) -> Result<(), Error> {
+        let trimmed = |field: &Option<String>| {
+            field
+                .as_deref()
+                .map(str::trim)
+                .filter(|f| !f.is_empty())
+                .map(str::to_owned)
+        };
+        let (description, website, logo_url, location) = (
+            trimmed(&self.description),
+            trimmed(&self.website),
+            trimmed(&self.logo_url),
+            trimmed(&self.location),
+        );
+        if description
+            .as_ref()
+            .is_some_and(|d| d.chars().count() > MAX_DESCRIPTION_LENGTH)
+            || location
+                .as_ref()
+                .is_some_and(|l| l.chars().count() > MAX_FIELD_LENGTH)
+            || [&website, &logo_url]
+                .iter()
+                .any(|url| url.as_deref().is_some_and(|u| !valid_url(u)))
+        {
+            return Err(Error::ImproperProfile);
+        }
+        sqlx::query!(
+            r#"UPDATE companies SET description = $2, website = $3, logo_url = $4, location = $5
+            WHERE userid = $1"#,
+            userid,
+            description,
+            website,
+            logo_url,
+            location
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyCompanyRequest {
+    userid: Uuid,
+    verified: bool,
+}
+impl VerifyCompanyRequest {
+    pub async fn execute(&self, pool: &Pool<Postgres>) -> Result<(), Error> {
+        let verified_at = self.verified.then(OffsetDateTime::now_utc);
+        let updated = sqlx::query!(
+            "UPDATE companies SET verified_at = $2 WHERE userid = $1",
+            self.userid,
+            verified_at
+        )
+        .execute(pool)
+        .await?;
+        if updated.rows_affected() == 0 {
+            return Err(Error::Forbidden);
+        }
+        Ok(())
+    }
+}
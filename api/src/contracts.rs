@@ -0,0 +1,442 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use time::{format_description::FormatItem, macros::format_description, Date, OffsetDateTime};
+use uuid::Uuid;
+
+use crate::error::Error;
+use crate::jobs::{ContractType, JobHours, JobMode, PayUnit};
+use crate::notifications::{notify, Event};
+use crate::pdf::text_document;
+use crate::shifts::TIME_ZONE;
+use crate::users::{age_on, ADULT_AGE};
+
+/// Version 1 of each template, the first line is the title
+const DEFAULT_TEMPLATES: [(ContractType, &str); 4] = [
+    (
+        ContractType::Praca,
+        include_str!("../data/contracts/praca.txt"),
+    ),
+    (
+        ContractType::Dzielo,
+        include_str!("../data/contracts/dzielo.txt"),
+    ),
+    (
+        ContractType::Zlecenie,
+        include_str!("../data/contracts/zlecenie.txt"),
+    ),
+    (ContractType::Tmp, include_str!("../data/contracts/tmp.txt")),
+];
+const PLACEHOLDERS: [&str; 17] = [
+    "date",
+    "company_name",
+    "nip",
+    "company_representative",
+    "candidate_name",
+    "candidate_birth_date",
+    "candidate_email",
+    "guardian_name",
+    "guardian_consent",
+    "contract_type",
+    "job_description",
+    "job_location",
+    "mode",
+    "hours",
+    "pay",
+    "jobid",
+    "applicationid",
+];
+/// Art. 190 of the Labour Code, younger children are only employed in the arts, with the
+/// consent of a labour inspector
+const EMPLOYMENT_AGE: i32 = 15;
+/// Art. 15 of the Civil Code, younger children can't make contracts even with their
+/// guardian's consent
+const CIVIL_CONTRACT_AGE: i32 = 13;
+/// Left for the parties to fill in by hand when the platform doesn't know the value
+const BLANK: &str = "....................";
+pub(crate) const POLISH_DATE: &[FormatItem] = format_description!("[day].[month].[year]");
+
+pub(crate) fn contract_name(contract_type: &ContractType) -> &'static str {
+    match contract_type {
+        ContractType::Praca => "umowa o pracę",
+        ContractType::Dzielo => "umowa o dzieło",
+        ContractType::Zlecenie => "umowa zlecenia",
+        ContractType::Tmp => "umowa o pracę tymczasową",
+    }
+}
+
+/// Whether the candidate is old enough for a contract of the type on the date, candidates
+/// without a birth date are treated as juvenile workers
+pub(crate) fn old_enough(
+    birth_date: Option<Date>,
+    contract_type: &ContractType,
+    date: Date,
+) -> bool {
+    let minimum_age = match contract_type {
+        ContractType::Praca | ContractType::Tmp => EMPLOYMENT_AGE,
+        ContractType::Dzielo | ContractType::Zlecenie => CIVIL_CONTRACT_AGE,
+    };
+    birth_date.is_none_or(|b| age_on(b, date) >= minimum_age)
+}
+
+fn mode_name(mode: &JobMode) -> &'static str {
+    match mode {
+        JobMode::Stationary => "stacjonarnie",
+        JobMode::Home => "zdalnie",
+        JobMode::Hybrid => "hybrydowo",
+        JobMode::Mobile => "w terenie",
+    }
+}
+
+fn hours_name(hours: &JobHours) -> &'static str {
+    match hours {
+        JobHours::Weekend => "w weekendy",
+        JobHours::Holiday => "w wakacje",
+        JobHours::Week => "w dni robocze",
+        JobHours::Elastic => "elastyczne godziny",
+        JobHours::Shifts => "zmiany według grafiku",
+    }
+}
+
+pub(crate) fn pay_text(
+    amount: Option<i64>,
+    unit: Option<PayUnit>,
+    currency: Option<String>,
+    gross: Option<bool>,
+) -> String {
+    let (Some(amount), Some(unit)) = (amount, unit) else {
+        return BLANK.to_owned();
+    };
+    format!(
+        "{},{:02} {} {} {}",
+        amount / 100,
+        amount % 100,
+        currency.unwrap_or_else(|| "PLN".to_owned()),
+        match gross {
+            Some(false) => "netto",
+            _ => "brutto",
+        },
+        match unit {
+            PayUnit::Hour => "za godzinę",
+            PayUnit::Task => "za zadanie",
+            PayUnit::Month => "miesięcznie",
+        }
+    )
+}
+
+/// Whether every `{placeholder}` in the text is a known one
+fn placeholders_known(text: &str) -> bool {
+    text.split('{')
+        .skip(1)
+        .all(|part| match part.split_once('}') {
+            Some((name, _)) => PLACEHOLDERS.contains(&name),
+            None => true,
+        })
+}
+
+fn fill(text: &str, values: &[(&str, String)]) -> String {
+    values.iter().fold(text.to_owned(), |text, (name, value)| {
+        text.replace(&format!("{{{}}}", name), value)
+    })
+}
+
+pub async fn seed_contract_templates(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    for (contract_type, template) in DEFAULT_TEMPLATES {
+        let (title, body) = template.split_once('\n').unwrap_or((template, ""));
+        sqlx::query!(
+            r#"INSERT INTO contract_templates (contract_type, version, title, body, creation_time)
+            VALUES ($1, 1, $2, $3, $4)
+            ON CONFLICT DO NOTHING"#,
+            contract_type as _,
+            title,
+            body,
+            OffsetDateTime::now_utc()
+        )
+        .execute(pool)
+        .await?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+pub struct ContractTemplate {
+    contract_type: ContractType,
+    version: i32,
+    title: String,
+    body: String,
+    creation_time: OffsetDateTime,
+}
+
+/// Every version of the templates, the newest first
+pub async fn get_contract_templates(
+    pool: &Pool<Postgres>,
+) -> Result<Vec<ContractTemplate>, sqlx::Error> {
+    sqlx::query_as!(
+        ContractTemplate,
+        r#"SELECT
+            contract_type "contract_type: ContractType",
+            version,
+            title,
+            body,
+            creation_time
+        FROM contract_templates
+        ORDER BY contract_type, version DESC"#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AddContractTemplateRequest {
+    contract_type: ContractType,
+    title: String,
+    body: String,
+}
+impl AddContractTemplateRequest {
+    /// Adds the next version, contracts already generated keep the version they were made from
+    pub async fn execute(&self, pool: &Pool<Postgres>) -> Result<ContractTemplate, Error> {
+        if self.title.trim().is_empty()
+            || self.body.trim().is_empty()
+            || !placeholders_known(&self.title)
+            || !placeholders_known(&self.body)
+        {
+            return Err(Error::ImproperContractTemplate);
+        }
+        Ok(sqlx::query_as!(
+            ContractTemplate,
+            r#"INSERT INTO contract_templates (contract_type, version, title, body, creation_time)
+            SELECT $1, coalesce(max(version), 0) + 1, $2, $3, $4
+            FROM contract_templates
+            WHERE contract_type = $1
+            RETURNING
+                contract_type "contract_type: ContractType",
+                version,
+                title,
+                body,
+                creation_time"#,
+            self.contract_type.clone() as _,
+            self.title.trim(),
+            self.body,
+            OffsetDateTime::now_utc()
+        )
+        .fetch_one(pool)
+        .await?)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Contract {
+    contractid: i32,
+    applicationid: i32,
+    contract_type: ContractType,
+    template_version: i32,
+    creation_time: OffsetDateTime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HireRequest {
+    applicationid: i32,
+}
+impl HireRequest {
+    /// Marks the applicant hired and generates their contract from the newest template
+    /// for the job's contract type. Minors need a confirmed guardian to be named in it
+    pub async fn execute(&self, pool: &Pool<Postgres>, owner: Uuid) -> Result<Contract, Error> {
+        let mut tx = pool.begin().await?;
+        let hire = sqlx::query!(
+            r#"SELECT
+                applications.jobid,
+                applications.userid,
+                applications.hired_at,
+                users.full_name,
+                users.email,
+                users.birth_date,
+                users.guardian_name,
+                users.guardian_confirmed_at,
+                companies.company_name,
+                companies.nip,
+                companies.full_name "company_representative",
+                jobs.owner,
+                jobs.job_location,
+                jobs.description,
+                jobs.contract_type "contract_type: ContractType",
+                jobs.mode "mode: JobMode",
+                jobs.hours "hours: JobHours",
+                jobs.pay_amount,
+                jobs.pay_unit "pay_unit: PayUnit",
+                jobs.pay_currency,
+                jobs.pay_gross,
+                (now() AT TIME ZONE $2)::date "today!"
+            FROM applications
+            JOIN users ON users.userid = applications.userid
+            JOIN jobs ON jobs.jobid = applications.jobid
+            JOIN companies ON companies.userid = jobs.owner
+            WHERE applications.applicationid = $1
+            FOR UPDATE OF applications"#,
+            self.applicationid,
+            TIME_ZONE
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .filter(|hire| hire.owner == owner)
+        .ok_or(Error::Forbidden)?;
+        if hire.hired_at.is_some() {
+            return Err(Error::AlreadyHired);
+        }
+        let adult = hire
+            .birth_date
+            .is_some_and(|b| age_on(b, hire.today) >= ADULT_AGE);
+        let guardian_consent = match (&hire.guardian_name, adult) {
+            (_, true) => String::new(),
+            (Some(guardian), false) if hire.guardian_confirmed_at.is_some() => {
+                format!(
+                    ", działającym za zgodą przedstawiciela ustawowego {}",
+                    guardian
+                )
+            }
+            (_, false) => return Err(Error::MissingGuardian),
+        };
+
+        let template = sqlx::query!(
+            r#"SELECT version, title, body
+            FROM contract_templates
+            WHERE contract_type = $1
+            ORDER BY version DESC
+            LIMIT 1"#,
+            hire.contract_type.clone() as _
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        let date = |d: time::Date| d.format(POLISH_DATE).unwrap_or_default();
+        let values = [
+            ("date", date(hire.today)),
+            ("company_name", hire.company_name),
+            ("nip", hire.nip.to_string()),
+            ("company_representative", hire.company_representative),
+            ("candidate_name", hire.full_name),
+            (
+                "candidate_birth_date",
+                hire.birth_date
+                    .map(date)
+                    .unwrap_or_else(|| BLANK.to_owned()),
+            ),
+            ("candidate_email", hire.email),
+            (
+                "guardian_name",
+                hire.guardian_name.unwrap_or_else(|| BLANK.to_owned()),
+            ),
+            ("guardian_consent", guardian_consent),
+            (
+                "contract_type",
+                contract_name(&hire.contract_type).to_owned(),
+            ),
+            (
+                "job_description",
+                hire.description.unwrap_or_else(|| BLANK.to_owned()),
+            ),
+            ("job_location", hire.job_location),
+            ("mode", mode_name(&hire.mode).to_owned()),
+            ("hours", hours_name(&hire.hours).to_owned()),
+            (
+                "pay",
+                pay_text(
+                    hire.pay_amount,
+                    hire.pay_unit,
+                    hire.pay_currency,
+                    hire.pay_gross,
+                ),
+            ),
+            ("jobid", hire.jobid.to_string()),
+            ("applicationid", self.applicationid.to_string()),
+        ];
+        let document = text_document(
+            &fill(&template.title, &values),
+            &fill(&template.body, &values),
+        );
+
+        let now = OffsetDateTime::now_utc();
+        sqlx::query!(
+            "UPDATE applications SET hired_at = $2 WHERE applicationid = $1",
+            self.applicationid,
+            now
+        )
+        .execute(&mut *tx)
+        .await?;
+        let contract = sqlx::query_as!(
+            Contract,
+            r#"INSERT INTO contracts
+                (applicationid, contract_type, template_version, document, creation_time)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING
+                contractid,
+                applicationid,
+                contract_type "contract_type: ContractType",
+                template_version,
+                creation_time"#,
+            self.applicationid,
+            hire.contract_type as _,
+            template.version,
+            document,
+            now
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        notify(
+            pool,
+            hire.userid,
+            Event::Hired {
+                applicationid: self.applicationid,
+                contractid: contract.contractid,
+            },
+        )
+        .await;
+        Ok(contract)
+    }
+}
+
+/// Contracts of the hired user, or of everyone the company hired
+pub async fn get_contracts(
+    pool: &Pool<Postgres>,
+    userid: Uuid,
+) -> Result<Vec<Contract>, sqlx::Error> {
+    sqlx::query_as!(
+        Contract,
+        r#"SELECT
+            contracts.contractid,
+            contracts.applicationid,
+            contracts.contract_type "contract_type: ContractType",
+            contracts.template_version,
+            contracts.creation_time
+        FROM contracts
+        JOIN applications ON applications.applicationid = contracts.applicationid
+        JOIN jobs ON jobs.jobid = applications.jobid
+        WHERE applications.userid = $1 OR jobs.owner = $1
+        ORDER BY contracts.creation_time DESC"#,
+        userid
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ContractRequest {
+    pub(crate) contractid: i32,
+}
+impl ContractRequest {
+    /// The generated PDF, for the hired user and the company
+    pub async fn document(&self, pool: &Pool<Postgres>, userid: Uuid) -> Result<Vec<u8>, Error> {
+        sqlx::query_scalar!(
+            r#"SELECT contracts.document
+            FROM contracts
+            JOIN applications ON applications.applicationid = contracts.applicationid
+            JOIN jobs ON jobs.jobid = applications.jobid
+            WHERE contracts.contractid = $1 AND
+                (applications.userid = $2 OR jobs.owner = $2)"#,
+            self.contractid,
+            userid
+        )
+        .fetch_optional(pool)
+        .await?
+        .ok_or(Error::Forbidden)
+    }
+}
@@ -0,0 +1,371 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{types::Json, Pool, Postgres};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::alerts::spawn_match_job;
+use crate::error::Error;
+use crate::jobs::{
+    get_job, insert_job, ContractType, Job, JobCreateRequest, JobHours, JobMode, PayUnit,
+};
+use crate::notifications::{notify, Event};
+use crate::tags::tag_strings;
+
+const PUBLISH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// The fields of a `JobCreateRequest`, any of which may still be missing
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct JobDraft {
+    #[serde(default)]
+    job_location: Option<String>,
+    #[serde(default)]
+    contract_type: Option<ContractType>,
+    #[serde(default)]
+    mode: Option<JobMode>,
+    #[serde(default)]
+    hours: Option<JobHours>,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    tags: Option<Vec<String>>,
+    #[serde(default)]
+    pay_amount: Option<i64>,
+    #[serde(default)]
+    pay_unit: Option<PayUnit>,
+    #[serde(default)]
+    pay_currency: Option<String>,
+    #[serde(default)]
+    pay_gross: Option<bool>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub(crate) expires_at: Option<OffsetDateTime>,
+}
+impl JobDraft {
+    /// The request to post the job with, fails when a required field is missing
+    pub fn complete(&self) -> Result<JobCreateRequest, Error> {
+        let draft = self.clone();
+        Ok(JobCreateRequest {
+            job_location: draft.job_location.ok_or(Error::IncompleteDraft)?,
+            contract_type: draft.contract_type.ok_or(Error::IncompleteDraft)?,
+            mode: draft.mode.ok_or(Error::IncompleteDraft)?,
+            hours: draft.hours.ok_or(Error::IncompleteDraft)?,
+            description: draft.description.ok_or(Error::IncompleteDraft)?,
+            tags: draft.tags.unwrap_or_default(),
+            pay_amount: draft.pay_amount,
+            pay_unit: draft.pay_unit,
+            pay_currency: draft.pay_currency,
+            pay_gross: draft.pay_gross,
+            expires_at: draft.expires_at,
+        })
+    }
+    /// This draft with every field set in `overrides` replaced
+    pub fn merge(&self, overrides: &JobDraft) -> JobDraft {
+        let overrides = overrides.clone();
+        let draft = self.clone();
+        JobDraft {
+            job_location: overrides.job_location.or(draft.job_location),
+            contract_type: overrides.contract_type.or(draft.contract_type),
+            mode: overrides.mode.or(draft.mode),
+            hours: overrides.hours.or(draft.hours),
+            description: overrides.description.or(draft.description),
+            tags: overrides.tags.or(draft.tags),
+            pay_amount: overrides.pay_amount.or(draft.pay_amount),
+            pay_unit: overrides.pay_unit.or(draft.pay_unit),
+            pay_currency: overrides.pay_currency.or(draft.pay_currency),
+            pay_gross: overrides.pay_gross.or(draft.pay_gross),
+            expires_at: overrides.expires_at.or(draft.expires_at),
+        }
+    }
+}
+
+/// The fields a company entered for the job, the expiry is left for the new listing to pick
+impl From<Job> for JobDraft {
+    fn from(job: Job) -> Self {
+        JobDraft {
+            tags: Some(tag_strings(&job.tags)),
+            job_location: job.job_location,
+            contract_type: Some(job.contract_type),
+            mode: Some(job.mode),
+            hours: Some(job.hours),
+            description: job.description,
+            pay_amount: job.pay_amount,
+            pay_unit: job.pay_unit,
+            pay_currency: job.pay_currency,
+            pay_gross: job.pay_gross,
+            expires_at: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Draft {
+    draftid: i32,
+    owner: Uuid,
+    job: Json<JobDraft>,
+    publish_at: Option<OffsetDateTime>,
+    creation_time: OffsetDateTime,
+    update_time: OffsetDateTime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SaveDraftRequest {
+    /// Creates a new draft when missing
+    #[serde(default)]
+    draftid: Option<i32>,
+    job: JobDraft,
+}
+impl SaveDraftRequest {
+    /// Editing a scheduled draft so that it can't be published any more unschedules it
+    pub async fn execute(&self, pool: &Pool<Postgres>, owner: Uuid) -> Result<Draft, Error> {
+        let now = OffsetDateTime::now_utc();
+        let publishable = self
+            .job
+            .complete()
+            .and_then(|request| request.into_job(owner))
+            .is_ok();
+        let draft = match self.draftid {
+            None => Some(create_draft(pool, owner, &self.job).await?),
+            Some(draftid) => {
+                sqlx::query_as!(
+                    Draft,
+                    r#"UPDATE job_drafts SET
+                        job = $3,
+                        update_time = $4,
+                        publish_at = CASE WHEN $5 THEN publish_at END
+                    WHERE draftid = $1 AND owner = $2
+                    RETURNING
+                        draftid,
+                        owner,
+                        job "job: Json<JobDraft>",
+                        publish_at,
+                        creation_time,
+                        update_time"#,
+                    draftid,
+                    owner,
+                    Json(&self.job) as _,
+                    now,
+                    publishable
+                )
+                .fetch_optional(pool)
+                .await?
+            }
+        };
+        draft.ok_or(Error::Forbidden)
+    }
+}
+
+pub async fn create_draft(
+    pool: &Pool<Postgres>,
+    owner: Uuid,
+    job: &JobDraft,
+) -> Result<Draft, sqlx::Error> {
+    sqlx::query_as!(
+        Draft,
+        r#"INSERT INTO job_drafts (owner, job, creation_time, update_time)
+        VALUES ($1, $2, $3, $3)
+        RETURNING
+            draftid,
+            owner,
+            job "job: Json<JobDraft>",
+            publish_at,
+            creation_time,
+            update_time"#,
+        owner,
+        Json(job) as _,
+        OffsetDateTime::now_utc()
+    )
+    .fetch_one(pool)
+    .await
+}
+
+pub async fn get_drafts(pool: &Pool<Postgres>, owner: Uuid) -> Result<Vec<Draft>, sqlx::Error> {
+    sqlx::query_as!(
+        Draft,
+        r#"SELECT
+            draftid,
+            owner,
+            job "job: Json<JobDraft>",
+            publish_at,
+            creation_time,
+            update_time
+        FROM job_drafts
+        WHERE owner = $1
+        ORDER BY update_time DESC"#,
+        owner
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DraftRequest {
+    draftid: i32,
+}
+impl DraftRequest {
+    async fn get(&self, pool: &Pool<Postgres>, owner: Uuid) -> Result<Draft, Error> {
+        sqlx::query_as!(
+            Draft,
+            r#"SELECT
+                draftid,
+                owner,
+                job "job: Json<JobDraft>",
+                publish_at,
+                creation_time,
+                update_time
+            FROM job_drafts
+            WHERE draftid = $1 AND owner = $2"#,
+            self.draftid,
+            owner
+        )
+        .fetch_optional(pool)
+        .await?
+        .ok_or(Error::Forbidden)
+    }
+    pub async fn delete(&self, pool: &Pool<Postgres>, owner: Uuid) -> Result<(), Error> {
+        let deleted = sqlx::query!(
+            "DELETE FROM job_drafts WHERE draftid = $1 AND owner = $2",
+            self.draftid,
+            owner
+        )
+        .execute(pool)
+        .await?;
+        if deleted.rows_affected() == 0 {
+            return Err(Error::Forbidden);
+        }
+        Ok(())
+    }
+    /// Publishes the draft right away, job alerts are left to the caller
+    pub async fn publish(&self, pool: &Pool<Postgres>, owner: Uuid) -> Result<Job, Error> {
+        let draft = self.get(pool, owner).await?;
+        publish_draft(pool, &draft.job, draft.draftid, owner).await
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DuplicateRequest {
+    jobid: i32,
+}
+impl DuplicateRequest {
+    /// A new draft with the fields of one of the company's jobs
+    pub async fn execute(&self, pool: &Pool<Postgres>, owner: Uuid) -> Result<Draft, Error> {
+        let job = match get_job(pool, self.jobid).await? {
+            Some(job) if job.owner == owner => job,
+            _ => return Err(Error::Forbidden),
+        };
+        Ok(create_draft(pool, owner, &JobDraft::from(job)).await?)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ScheduleRequest {
+    draftid: i32,
+    /// Unschedules the draft when missing
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    publish_at: Option<OffsetDateTime>,
+}
+impl ScheduleRequest {
+    pub async fn execute(&self, pool: &Pool<Postgres>, owner: Uuid) -> Result<Draft, Error> {
+        let draft = DraftRequest {
+            draftid: self.draftid,
+        }
+        .get(pool, owner)
+        .await?;
+        if let Some(publish_at) = self.publish_at {
+            if publish_at <= OffsetDateTime::now_utc() {
+                return Err(Error::ImproperSchedule);
+            }
+            draft.job.complete()?.into_job(owner)?;
+            if draft.job.expires_at.is_some_and(|t| t <= publish_at) {
+                return Err(Error::ImproperExpiry);
+            }
+        }
+        Ok(sqlx::query_as!(
+            Draft,
+            r#"UPDATE job_drafts SET publish_at = $2
+            WHERE draftid = $1
+            RETURNING
+                draftid,
+                owner,
+                job "job: Json<JobDraft>",
+                publish_at,
+                creation_time,
+                update_time"#,
+            self.draftid,
+            self.publish_at
+        )
+        .fetch_one(pool)
+        .await?)
+    }
+}
+
+async fn publish_draft(
+    pool: &Pool<Postgres>,
+    draft: &JobDraft,
+    draftid: i32,
+    owner: Uuid,
+) -> Result<Job, Error> {
+    let job = draft.complete()?.into_job(owner)?;
+    // Together, so a draft is never left behind to be published twice
+    let mut tx = pool.begin().await?;
+    let job = insert_job(pool, &mut tx, &job).await?;
+    let deleted = sqlx::query!("DELETE FROM job_drafts WHERE draftid = $1", draftid)
+        .execute(&mut *tx)
+        .await?;
+    if deleted.rows_affected() == 0 {
+        return Err(Error::Forbidden);
+    }
+    tx.commit().await?;
+    Ok(job)
+}
+
+/// Publishes the drafts whose time has come and sends their job alerts.
+/// A draft that can't be published is unscheduled and its owner notified, the rest are still published
+pub async fn publish_scheduled(pool: &Pool<Postgres>) -> Result<(), Error> {
+    // Unscheduling first claims the drafts, so no other instance publishes them as well
+    let drafts = sqlx::query!(
+        r#"UPDATE job_drafts SET publish_at = NULL
+        WHERE publish_at <= $1
+        RETURNING draftid, owner, job "job: Json<JobDraft>""#,
+        OffsetDateTime::now_utc()
+    )
+    .fetch_all(pool)
+    .await?;
+
+    for draft in drafts {
+        match publish_draft(pool, &draft.job, draft.draftid, draft.owner).await {
+            Ok(job) => {
+                notify(
+                    pool,
+                    draft.owner,
+                    Event::DraftPublished {
+                        draftid: draft.draftid,
+                        jobid: job.jobid,
+                    },
+                )
+                .await;
+                spawn_match_job(pool, &job);
+            }
+            Err(e) => {
+                println!("Publishing draft {} failed: {}", draft.draftid, e);
+                notify(
+                    pool,
+                    draft.owner,
+                    Event::DraftFailed {
+                        draftid: draft.draftid,
+                    },
+                )
+                .await;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn publish_task(pool: Pool<Postgres>) {
+    let mut interval = tokio::time::interval(PUBLISH_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Err(e) = publish_scheduled(&pool).await {
+            println!("Scheduled publication error: {}", e);
+        }
+    }
+}
@@ -3,17 +3,61 @@ use core::fmt;
 use warp::reject::{Reject, Rejection};
 
 #[derive(Debug)]
+#[allow(clippy::upper_case_acronyms, clippy::enum_variant_names)]
 pub enum Error {
     NoSuchUser,
     BadPassword,
     ImproperNIP,
+    ImproperPay,
+    PayBelowMinimum,
+    ImproperPayFilter,
+    UnknownLocation,
+    ImproperTags,
+    NoSuchTag,
+    ImproperExpiry,
+    JobArchived,
+    IncompleteDraft,
+    ImproperSchedule,
+    ImproperBirthDate,
+    ImproperShift,
+    ShiftClosed,
+    ShiftFull,
+    ShiftOverlaps,
+    WorkingTimeExceeded,
+    ImproperInterview,
+    InterviewClosed,
+    InterviewNotConfirmed,
+    ImproperGuardian,
+    MissingGuardian,
+    AlreadyHired,
+    ImproperContractTemplate,
+    ImproperOffer,
+    AlreadyAccepted,
+    DocumentMismatch,
+    UnknownDocument,
+    NotHired,
+    ImproperTimesheet,
+    TimesheetOverlaps,
+    TimesheetReviewed,
+    NotCompleted,
+    ReviewNotAllowed,
+    ImproperReview,
+    AlreadyReviewed,
+    AlreadyReplied,
+    ImproperProfile,
+    ImproperRange,
+    NotVerified,
+    AlreadyInvited,
     SQLX(sqlx::Error),
     JWT(jsonwebtoken::errors::Error),
     UUID(uuid::Error),
+    JSON(serde_json::Error),
+    Mail(String),
     Forbidden,
     NoAuthHeaderError,
     InvalidAuthHeaderError,
     Expired,
+    EmptyMessage,
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -31,6 +75,11 @@ impl From<sqlx::Error> for Error {
         Error::SQLX(value)
     }
 }
+impl From<serde_json::Error> for Error {
+    fn from(value: serde_json::Error) -> Self {
+        Error::JSON(value)
+    }
+}
 impl From<jsonwebtoken::errors::Error> for Error {
     fn from(value: jsonwebtoken::errors::Error) -> Self {
         Error::JWT(value)
@@ -46,12 +95,63 @@ impl fmt::Display for Error {
                 Error::BadPassword => "The password is incorrect".to_owned(),
                 Error::SQLX(e) => format!("Sqlx error: {}", e),
                 Error::ImproperNIP => "The nip is incorrect".to_owned(),
+                Error::ImproperPay => "The pay is incorrect".to_owned(),
+                Error::ImproperPayFilter => "min_pay needs a pay_unit".to_owned(),
+                Error::UnknownLocation => "The location is not known".to_owned(),
+                Error::ImproperTags => "The tags are incorrect".to_owned(),
+                Error::NoSuchTag => "There is no such tag".to_owned(),
+                Error::ImproperExpiry => "The expiry date is incorrect".to_owned(),
+                Error::JobArchived => "The job is no longer open".to_owned(),
+                Error::IncompleteDraft => "The draft is missing required fields".to_owned(),
+                Error::ImproperSchedule => "The publication time is incorrect".to_owned(),
+                Error::ImproperBirthDate => "The birth date is incorrect".to_owned(),
+                Error::ImproperShift => "The shift is incorrect".to_owned(),
+                Error::ShiftClosed => "The shift can no longer be signed up for".to_owned(),
+                Error::ShiftFull => "The shift is full".to_owned(),
+                Error::ShiftOverlaps => "The shift overlaps another shift".to_owned(),
+                Error::WorkingTimeExceeded =>
+                    "The shift exceeds the working time allowed for minors".to_owned(),
+                Error::ImproperInterview => "The interview slots are incorrect".to_owned(),
+                Error::InterviewClosed => "The interview can no longer be changed".to_owned(),
+                Error::InterviewNotConfirmed => "The interview is not confirmed".to_owned(),
+                Error::ImproperGuardian => "The guardian details are incorrect".to_owned(),
+                Error::MissingGuardian => {
+                    "The candidate has no guardian set or the guardian didn't confirm yet"
+                        .to_owned()
+                }
+                Error::AlreadyHired => "The applicant is already hired".to_owned(),
+                Error::ImproperContractTemplate => "The contract template is incorrect".to_owned(),
+                Error::ImproperOffer => "The offer is incorrect".to_owned(),
+                Error::AlreadyAccepted => "The offer is already accepted".to_owned(),
+                Error::DocumentMismatch =>
+                    "The document doesn't match the one being accepted".to_owned(),
+                Error::UnknownDocument => "There is no document with this hash".to_owned(),
+                Error::NotHired => "The candidate isn't hired".to_owned(),
+                Error::ImproperTimesheet => "The timesheet entry is incorrect".to_owned(),
+                Error::TimesheetOverlaps => "The timesheet entry overlaps another entry".to_owned(),
+                Error::TimesheetReviewed => "The timesheet entry is already reviewed".to_owned(),
+                Error::NotCompleted => "The engagement isn't completed".to_owned(),
+                Error::ReviewNotAllowed =>
+                    "Only engagements with approved work can be reviewed".to_owned(),
+                Error::ImproperReview => "The review is incorrect".to_owned(),
+                Error::AlreadyReviewed => "The engagement is already reviewed".to_owned(),
+                Error::AlreadyReplied => "The review already has a reply".to_owned(),
+                Error::ImproperProfile => "Improper profile".to_owned(),
+                Error::ImproperRange => "Improper date range".to_owned(),
+                Error::NotVerified => "Only verified companies can do this".to_owned(),
+                Error::AlreadyInvited => "The candidate was already invited or applied".to_owned(),
+                Error::PayBelowMinimum => {
+                    "The hourly pay is below the statutory minimum rate".to_owned()
+                }
                 Error::JWT(e) => format!("JWT error: {}", e),
                 Error::UUID(e) => format!("UUID error: {}", e),
+                Error::JSON(e) => format!("JSON error: {}", e),
+                Error::Mail(e) => format!("Mail error: {}", e),
                 Error::Forbidden => "Forbidden".to_owned(),
                 Error::NoAuthHeaderError => "No auth header".to_owned(),
                 Error::InvalidAuthHeaderError => "Invalid auth header".to_owned(),
                 Error::Expired => "JWT expired".to_owned(),
+                Error::EmptyMessage => "The message is empty".to_owned(),
             }
         )
     }
@@ -63,12 +163,55 @@ impl std::error::Error for Error {
             Error::BadPassword => None,
             Error::SQLX(e) => Some(e),
             Error::ImproperNIP => None,
+            Error::ImproperPay => None,
+            Error::PayBelowMinimum => None,
+            Error::ImproperPayFilter => None,
+            Error::UnknownLocation => None,
+            Error::ImproperTags => None,
+            Error::NoSuchTag => None,
+            Error::ImproperExpiry => None,
+            Error::JobArchived => None,
+            Error::IncompleteDraft => None,
+            Error::ImproperSchedule => None,
+            Error::ImproperBirthDate => None,
+            Error::ImproperShift => None,
+            Error::ShiftClosed => None,
+            Error::ShiftFull => None,
+            Error::ShiftOverlaps => None,
+            Error::WorkingTimeExceeded => None,
+            Error::ImproperInterview => None,
+            Error::InterviewClosed => None,
+            Error::InterviewNotConfirmed => None,
+            Error::ImproperGuardian => None,
+            Error::MissingGuardian => None,
+            Error::AlreadyHired => None,
+            Error::ImproperContractTemplate => None,
+            Error::ImproperOffer => None,
+            Error::AlreadyAccepted => None,
+            Error::DocumentMismatch => None,
+            Error::UnknownDocument => None,
+            Error::NotHired => None,
+            Error::ImproperTimesheet => None,
+            Error::TimesheetOverlaps => None,
+            Error::TimesheetReviewed => None,
+            Error::NotCompleted => None,
+            Error::ReviewNotAllowed => None,
+            Error::ImproperReview => None,
+            Error::AlreadyReviewed => None,
+            Error::AlreadyReplied => None,
+            Error::ImproperProfile => None,
+            Error::ImproperRange => None,
+            Error::NotVerified => None,
+            Error::AlreadyInvited => None,
             Error::JWT(e) => Some(e),
             Error::UUID(e) => Some(e),
+            Error::JSON(e) => Some(e),
+            Error::Mail(_) => None,
             Error::Forbidden => None,
             Error::NoAuthHeaderError => None,
             Error::InvalidAuthHeaderError => None,
             Error::Expired => None,
+            Error::EmptyMessage => None,
         }
     }
 
@@ -0,0 +1,177 @@
+use serde::Deserialize;
+use sqlx::{Pool, Postgres};
+use time::{Duration, OffsetDateTime};
+use uuid::Uuid;
+
+use crate::error::Error;
+use crate::jobs::{ContractType, Job, JobHours, JobMode, PayUnit};
+use crate::mail::send_mail;
+use crate::notifications::{notify, Event};
+
+/// Used when a job is posted or renewed without `expires_at`
+const DEFAULT_LIFETIME: Duration = Duration::days(30);
+pub(crate) const MAX_LIFETIME: Duration = Duration::days(90);
+/// How long before expiry the company is reminded
+const REMINDER_BEFORE: Duration = Duration::days(3);
+const CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+/// The requested expiry time, or the default one, it has to lie within `MAX_LIFETIME` from now
+pub fn expiry_time(requested: Option<OffsetDateTime>) -> Result<OffsetDateTime, Error> {
+    let now = OffsetDateTime::now_utc();
+    match requested {
+        None => Ok(now + DEFAULT_LIFETIME),
+        Some(t) if t > now && t <= now + MAX_LIFETIME => Ok(t),
+        Some(_) => Err(Error::ImproperExpiry),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RenewRequest {
+    jobid: i32,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    expires_at: Option<OffsetDateTime>,
+}
+impl RenewRequest {
+    /// Extends the job, an archived job is listed again
+    pub async fn execute(&self, pool: &Pool<Postgres>, owner: Uuid) -> Result<Job, Error> {
+        let expires_at = expiry_time(self.expires_at)?;
+        let job = sqlx::query_as!(
+            Job,
+            r#"UPDATE jobs SET
+                expires_at = $3,
+                archived_at = NULL,
+                expiry_reminded = false
+            WHERE jobid = $1 AND owner = $2
+            RETURNING
+                jobid,
+                owner,
+                creation_time,
+                job_location,
+                contract_type "contract_type: ContractType",
+                mode "mode: JobMode",
+                hours "hours: JobHours",
+                description,
+                tags,
+                pay_amount,
+                pay_unit "pay_unit: PayUnit",
+                pay_currency,
+                pay_gross,
+                city,
+                voivodeship,
+                postal_code,
+                latitude,
+                longitude,
+                expires_at,
+                archived_at"#,
+            self.jobid,
+            owner,
+            expires_at
+        )
+        .fetch_optional(pool)
+        .await?;
+        job.ok_or(Error::Forbidden)
+    }
+}
+
+async fn remind_expiry(
+    pool: &Pool<Postgres>,
+    jobid: i32,
+    owner: Uuid,
+    email: &str,
+    location: &str,
+    expires_at: OffsetDateTime,
+) -> Result<(), Error> {
+    send_mail(
+        email,
+        "Your job offer expires soon",
+        format!(
+            "#{} {} expires on {}, renew it to keep it listed.\n",
+            jobid,
+            location,
+            expires_at.date()
+        ),
+    )
+    .await?;
+    sqlx::query!(
+        "UPDATE jobs SET expiry_reminded = true WHERE jobid = $1",
+        jobid
+    )
+    .execute(pool)
+    .await?;
+    notify(pool, owner, Event::JobExpiring { jobid }).await;
+    Ok(())
+}
+
+/// Tells companies about their jobs expiring within `REMINDER_BEFORE`, once per job.
+/// A job is marked reminded only once its mail is sent, failed ones are retried on the next check
+pub async fn send_expiry_reminders(pool: &Pool<Postgres>) -> Result<(), Error> {
+    let now = OffsetDateTime::now_utc();
+    let jobs = sqlx::query!(
+        r#"SELECT jobs.jobid, jobs.owner, jobs.job_location, jobs.expires_at, companies.email
+        FROM jobs
+        JOIN companies ON companies.userid = jobs.owner
+        WHERE jobs.archived_at IS NULL AND
+            NOT jobs.expiry_reminded AND
+            jobs.expires_at > $1 AND
+            jobs.expires_at <= $2"#,
+        now,
+        now + REMINDER_BEFORE
+    )
+    .fetch_all(pool)
+    .await?;
+
+    for job in jobs {
+        let reminded = remind_expiry(
+            pool,
+            job.jobid,
+            job.owner,
+            &job.email,
+            &job.job_location,
+            job.expires_at,
+        )
+        .await;
+        if let Err(e) = reminded {
+            println!("Expiry reminder error for job {}: {}", job.jobid, e);
+        }
+    }
+    Ok(())
+}
+
+/// Archives the expired jobs one by one, so a failure leaves the others unaffected
+pub async fn archive_expired(pool: &Pool<Postgres>) -> Result<(), Error> {
+    let now = OffsetDateTime::now_utc();
+    let jobs = sqlx::query!(
+        "SELECT jobid, owner FROM jobs WHERE archived_at IS NULL AND expires_at <= $1",
+        now
+    )
+    .fetch_all(pool)
+    .await?;
+
+    for job in jobs {
+        let archived = sqlx::query!(
+            "UPDATE jobs SET archived_at = $2 WHERE jobid = $1 AND archived_at IS NULL",
+            job.jobid,
+            now
+        )
+        .execute(pool)
+        .await;
+        match archived {
+            Ok(_) => notify(pool, job.owner, Event::JobArchived { jobid: job.jobid }).await,
+            Err(e) => println!("Archival error for job {}: {}", job.jobid, e),
+        }
+    }
+    Ok(())
+}
+
+pub async fn expiry_task(pool: Pool<Postgres>) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Err(e) = send_expiry_reminders(&pool).await {
+            println!("Expiry reminder error: {}", e);
+        }
+        if let Err(e) = archive_expired(&pool).await {
+            println!("Archival error: {}", e);
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use time::{format_description::FormatItem, macros::format_description, OffsetDateTime, UtcOffset};
+
+const PRODID: &str = "-//NieletniPracuja//Interviews//PL";
+/// Content lines longer than this many octets are folded (RFC 5545, 3.1)
+const LINE_LIMIT: usize = 75;
+const UTC_TIME: &[FormatItem] = format_description!("[year][month][day]T[hour][minute][second]Z");
+
+pub struct CalendarEvent {
+    /// Globally unique and stable, clients update the event by it
+    pub uid: String,
+    pub start: OffsetDateTime,
+    pub end: OffsetDateTime,
+    pub summary: String,
+    pub location: String,
+    pub description: String,
+}
+
+/// A UTC DATE-TIME value
+fn utc_time(t: OffsetDateTime) -> String {
+    t.to_offset(UtcOffset::UTC)
+        .format(UTC_TIME)
+        .unwrap_or_default()
+}
+
+/// Escapes a TEXT value (RFC 5545, 3.3.11)
+fn text(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            ';' => escaped.push_str("\\;"),
+            ',' => escaped.push_str("\\,"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Appends the content line folded into CRLF terminated lines, never splitting a character
+fn push_line(out: &mut String, line: &str) {
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > LINE_LIMIT {
+            out.push_str("\r\n ");
+            length = 1;
+        }
+        out.push(c);
+        length += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+/// An iCalendar object with the events, stamped with the current time
+pub fn calendar(name: &str, events: &[CalendarEvent]) -> String {
+    let stamp = utc_time(OffsetDateTime::now_utc());
+    let mut out = String::new();
+    push_line(&mut out, "BEGIN:VCALENDAR");
+    push_line(&mut out, "VERSION:2.0");
+    push_line(&mut out, &format!("PRODID:{}", PRODID));
+    push_line(&mut out, "CALSCALE:GREGORIAN");
+    push_line(&mut out, &format!("X-WR-CALNAME:{}", text(name)));
+    for event in events {
+        push_line(&mut out, "BEGIN:VEVENT");
+        push_line(&mut out, &format!("UID:{}", event.uid));
+        push_line(&mut out, &format!("DTSTAMP:{}", stamp));
+        push_line(&mut out, &format!("DTSTART:{}", utc_time(event.start)));
+        push_line(&mut out, &format!("DTEND:{}", utc_time(event.end)));
+        push_line(&mut out, &format!("SUMMARY:{}", text(&event.summary)));
+        push_line(&mut out, &format!("LOCATION:{}", text(&event.location)));
+        push_line(
+            &mut out,
+            &format!("DESCRIPTION:{}", text(&event.description)),
+        );
+        push_line(&mut out, "STATUS:CONFIRMED");
+        push_line(&mut out, "END:VEVENT");
+    }
+    push_line(&mut out, "END:VCALENDAR");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fold(line: &str) -> String {
+        let mut out = String::new();
+        push_line(&mut out, line);
+        out
+    }
+
+    #[test]
+    fn short_lines_are_kept() {
+        assert_eq!(fold("SUMMARY:Rozmowa"), "SUMMARY:Rozmowa\r\n");
+        let line = "X".repeat(LINE_LIMIT);
+        assert_eq!(fold(&line), line.clone() + "\r\n");
+    }
+
+    #[test]
+    fn long_lines_are_folded() {
+        let line = "X".repeat(LINE_LIMIT + 5);
+        assert_eq!(
+            fold(&line),
+            format!("{}\r\n {}\r\n", "X".repeat(LINE_LIMIT), "X".repeat(5))
+        );
+    }
+
+    #[test]
+    fn characters_are_not_split() {
+        let line = "ż".repeat(100);
+        let folded = fold(&line);
+        for part in folded.split_terminator("\r\n") {
+            assert!(part.len() <= LINE_LIMIT);
+        }
+        assert_eq!(folded.replace("\r\n ", ""), line + "\r\n");
+    }
+}
@@ -0,0 +1,516 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use time::{format_description::FormatItem, macros::format_description, Duration, OffsetDateTime};
+use uuid::Uuid;
+
+use crate::applications::Application;
+use crate::error::Error;
+use crate::ical::{calendar, CalendarEvent};
+use crate::mail::send_mail;
+use crate::notifications::{notify, Event};
+use crate::shifts::TIME_ZONE;
+
+const MAX_SLOTS: usize = 10;
+const MAX_INTERVIEW_LENGTH: Duration = Duration::hours(4);
+/// How long before the interview both sides are reminded
+const REMINDER_BEFORE: Duration = Duration::hours(24);
+/// Past interviews kept in the calendar feed
+const FEED_HISTORY: Duration = Duration::days(30);
+const CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(15 * 60);
+const LOCAL_TIME: &[FormatItem] = format_description!("[year]-[month]-[day] [hour]:[minute]");
+
+#[derive(Serialize, Deserialize, Debug, sqlx::Type, Clone, Copy, PartialEq)]
+#[sqlx(type_name = "interview_status", rename_all = "lowercase")]
+pub enum InterviewStatus {
+    Proposed,
+    Accepted,
+    Declined,
+    Cancelled,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Interview {
+    interviewid: i32,
+    applicationid: i32,
+    start_time: OffsetDateTime,
+    end_time: OffsetDateTime,
+    location: String,
+    status: InterviewStatus,
+    creation_time: OffsetDateTime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Slot {
+    #[serde(with = "time::serde::rfc3339")]
+    start_time: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    end_time: OffsetDateTime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProposeRequest {
+    applicationid: i32,
+    slots: Vec<Slot>,
+    /// An address or a meeting link
+    location: String,
+}
+impl ProposeRequest {
+    /// Proposes the slots to the applicant, only the company owning the job may do it
+    pub async fn execute(
+        &self,
+        pool: &Pool<Postgres>,
+        owner: Uuid,
+    ) -> Result<Vec<Interview>, Error> {
+        let (applicant, job_owner) = match Application::get_parties(pool, self.applicationid).await
+        {
+            Ok(parties) => parties,
+            Err(sqlx::Error::RowNotFound) => return Err(Error::Forbidden),
+            Err(e) => return Err(e.into()),
+        };
+        if job_owner != owner {
+            return Err(Error::Forbidden);
+        }
+        let now = OffsetDateTime::now_utc();
+        if self.slots.is_empty()
+            || self.slots.len() > MAX_SLOTS
+            || self.location.trim().is_empty()
+            || self.slots.iter().any(|s| {
+                s.start_time <= now
+                    || s.end_time <= s.start_time
+                    || s.end_time - s.start_time > MAX_INTERVIEW_LENGTH
+            })
+        {
+            return Err(Error::ImproperInterview);
+        }
+        let starts: Vec<_> = self.slots.iter().map(|s| s.start_time).collect();
+        let ends: Vec<_> = self.slots.iter().map(|s| s.end_time).collect();
+        let interviews = sqlx::query_as!(
+            Interview,
+            r#"INSERT INTO interviews (applicationid, start_time, end_time, location, creation_time)
+            SELECT $1, slot.start_time, slot.end_time, $4, $5
+            FROM UNNEST($2::timestamptz[], $3::timestamptz[]) AS slot(start_time, end_time)
+            RETURNING
+                interviewid,
+                applicationid,
+                start_time,
+                end_time,
+                location,
+                status "status: InterviewStatus",
+                creation_time"#,
+            self.applicationid,
+            &starts,
+            &ends,
+            self.location.trim(),
+            now
+        )
+        .fetch_all(pool)
+        .await?;
+
+        notify(
+            pool,
+            applicant,
+            Event::InterviewProposed {
+                applicationid: self.applicationid,
+            },
+        )
+        .await;
+        Ok(interviews)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ApplicationInterviewsRequest {
+    applicationid: i32,
+}
+impl ApplicationInterviewsRequest {
+    pub async fn execute(
+        &self,
+        pool: &Pool<Postgres>,
+        userid: Uuid,
+    ) -> Result<Vec<Interview>, Error> {
+        if !Application::is_participant(pool, self.applicationid, userid).await? {
+            return Err(Error::Forbidden);
+        }
+        Ok(sqlx::query_as!(
+            Interview,
+            r#"SELECT
+                interviewid,
+                applicationid,
+                start_time,
+                end_time,
+                location,
+                status "status: InterviewStatus",
+                creation_time
+            FROM interviews
+            WHERE applicationid = $1
+            ORDER BY start_time"#,
+            self.applicationid
+        )
+        .fetch_all(pool)
+        .await?)
+    }
+}
+
+/// Proposed and accepted interviews of the user or the company which haven't ended yet
+pub async fn get_upcoming_interviews(
+    pool: &Pool<Postgres>,
+    userid: Uuid,
+) -> Result<Vec<Interview>, sqlx::Error> {
+    sqlx::query_as!(
+        Interview,
+        r#"SELECT
+            interviews.interviewid,
+            interviews.applicationid,
+            interviews.start_time,
+            interviews.end_time,
+            interviews.location,
+            interviews.status "status: InterviewStatus",
+            interviews.creation_time
+        FROM interviews
+        JOIN applications ON applications.applicationid = interviews.applicationid
+        JOIN jobs ON jobs.jobid = applications.jobid
+        WHERE (applications.userid = $1 OR jobs.owner = $1) AND
+            interviews.status IN ('proposed', 'accepted') AND
+            interviews.end_time > now()
+        ORDER BY interviews.start_time"#,
+        userid
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InterviewRequest {
+    pub(crate) interviewid: i32,
+}
+impl InterviewRequest {
+    /// Confirms the slot, the other slots still proposed for the application are declined
+    pub async fn accept(&self, pool: &Pool<Postgres>, userid: Uuid) -> Result<(), Error> {
+        let mut tx = pool.begin().await?;
+        // Accepting declines the sibling slots, so their locks keep two of them from being accepted
+        let interview = sqlx::query!(
+            r#"SELECT
+                interviews.applicationid,
+                interviews.status "status: InterviewStatus",
+                interviews.start_time,
+                jobs.owner
+            FROM interviews
+            JOIN applications ON applications.applicationid = interviews.applicationid
+            JOIN jobs ON jobs.jobid = applications.jobid
+            WHERE interviews.interviewid = $1 AND applications.userid = $2
+            FOR UPDATE OF interviews"#,
+            self.interviewid,
+            userid
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(Error::Forbidden)?;
+        if interview.status != InterviewStatus::Proposed
+            || interview.start_time <= OffsetDateTime::now_utc()
+        {
+            return Err(Error::InterviewClosed);
+        }
+        sqlx::query!(
+            r#"UPDATE interviews SET
+                status = CASE WHEN interviewid = $1
+                    THEN 'accepted'::interview_status
+                    ELSE 'declined'::interview_status
+                END
+            WHERE applicationid = $2 AND (interviewid = $1 OR status = 'proposed')"#,
+            self.interviewid,
+            interview.applicationid
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        notify(
+            pool,
+            interview.owner,
+            Event::InterviewAccepted {
+                interviewid: self.interviewid,
+            },
+        )
+        .await;
+        Ok(())
+    }
+    pub async fn decline(&self, pool: &Pool<Postgres>, userid: Uuid) -> Result<(), Error> {
+        let declined = sqlx::query!(
+            r#"UPDATE interviews SET status = 'declined'
+            FROM applications, jobs
+            WHERE applications.applicationid = interviews.applicationid AND
+                jobs.jobid = applications.jobid AND
+                interviews.interviewid = $1 AND
+                applications.userid = $2 AND
+                interviews.status = 'proposed'
+            RETURNING jobs.owner"#,
+            self.interviewid,
+            userid
+        )
+        .fetch_optional(pool)
+        .await?
+        .ok_or(Error::InterviewClosed)?;
+        notify(
+            pool,
+            declined.owner,
+            Event::InterviewDeclined {
+                interviewid: self.interviewid,
+            },
+        )
+        .await;
+        Ok(())
+    }
+    /// Either side may call off a proposed or accepted interview before it starts
+    pub async fn cancel(&self, pool: &Pool<Postgres>, userid: Uuid) -> Result<(), Error> {
+        let cancelled = sqlx::query!(
+            r#"UPDATE interviews SET status = 'cancelled'
+            FROM applications, jobs
+            WHERE applications.applicationid = interviews.applicationid AND
+                jobs.jobid = applications.jobid AND
+                interviews.interviewid = $1 AND
+                (applications.userid = $2 OR jobs.owner = $2) AND
+                interviews.status IN ('proposed', 'accepted') AND
+                interviews.start_time > now()
+            RETURNING applications.userid, jobs.owner"#,
+            self.interviewid,
+            userid
+        )
+        .fetch_optional(pool)
+        .await?
+        .ok_or(Error::InterviewClosed)?;
+        let recipient = if userid == cancelled.userid {
+            cancelled.owner
+        } else {
+            cancelled.userid
+        };
+        notify(
+            pool,
+            recipient,
+            Event::InterviewCancelled {
+                interviewid: self.interviewid,
+            },
+        )
+        .await;
+        Ok(())
+    }
+    /// The accepted interview as an iCalendar object
+    pub async fn ics(&self, pool: &Pool<Postgres>, userid: Uuid) -> Result<String, Error> {
+        let status = sqlx::query_scalar!(
+            r#"SELECT interviews.status "status: InterviewStatus"
+            FROM interviews
+            JOIN applications ON applications.applicationid = interviews.applicationid
+            JOIN jobs ON jobs.jobid = applications.jobid
+            WHERE interviews.interviewid = $1 AND
+                (applications.userid = $2 OR jobs.owner = $2)"#,
+            self.interviewid,
+            userid
+        )
+        .fetch_optional(pool)
+        .await?
+        .ok_or(Error::Forbidden)?;
+        if status != InterviewStatus::Accepted {
+            return Err(Error::InterviewNotConfirmed);
+        }
+        let events = calendar_events(pool, userid, Some(self.interviewid)).await?;
+        Ok(calendar("NieletniPracuja", &events))
+    }
+}
+
+/// Accepted interviews of the user or the company, described from their side.
+/// The feed leaves out those that ended more than `FEED_HISTORY` ago, a single interview is
+/// returned however old it is
+async fn calendar_events(
+    pool: &Pool<Postgres>,
+    userid: Uuid,
+    interviewid: Option<i32>,
+) -> Result<Vec<CalendarEvent>, sqlx::Error> {
+    let interviews = sqlx::query!(
+        r#"SELECT
+            interviews.interviewid,
+            interviews.applicationid,
+            interviews.start_time,
+            interviews.end_time,
+            interviews.location,
+            jobs.job_location,
+            companies.company_name,
+            users.full_name,
+            applications.userid
+        FROM interviews
+        JOIN applications ON applications.applicationid = interviews.applicationid
+        JOIN jobs ON jobs.jobid = applications.jobid
+        JOIN companies ON companies.userid = jobs.owner
+        JOIN users ON users.userid = applications.userid
+        WHERE (applications.userid = $1 OR jobs.owner = $1) AND
+            interviews.status = 'accepted' AND
+            (interviews.interviewid = $2 OR $2 IS NULL AND interviews.end_time > $3)
+        ORDER BY interviews.start_time"#,
+        userid,
+        interviewid,
+        OffsetDateTime::now_utc() - FEED_HISTORY
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(interviews
+        .into_iter()
+        .map(|i| CalendarEvent {
+            uid: format!("interview-{}@nieletnipracuja.pl", i.interviewid),
+            start: i.start_time,
+            end: i.end_time,
+            summary: if i.userid == userid {
+                format!("Interview: {}", i.company_name)
+            } else {
+                format!("Interview: {}", i.full_name)
+            },
+            location: i.location,
+            description: format!("Job: {}\nApplication #{}", i.job_location, i.applicationid),
+        })
+        .collect())
+}
+
+#[derive(Debug, Serialize)]
+pub struct CalendarToken {
+    token: Uuid,
+}
+
+/// The secret of the user's calendar feed, created on first use or replaced when `reset`
+pub async fn get_calendar_token(
+    pool: &Pool<Postgres>,
+    userid: Uuid,
+    reset: bool,
+) -> Result<CalendarToken, sqlx::Error> {
+    sqlx::query_as!(
+        CalendarToken,
+        r#"UPDATE login SET calendar_token = CASE
+            WHEN $2 OR calendar_token IS NULL THEN $3
+            ELSE calendar_token
+        END
+        WHERE userid = $1
+        RETURNING calendar_token "token!""#,
+        userid,
+        reset,
+        Uuid::new_v4()
+    )
+    .fetch_one(pool)
+    .await
+}
+
+/// The calendar feed of the user owning the token, calendar apps fetch it without a JWT
+pub async fn calendar_feed(pool: &Pool<Postgres>, token: Uuid) -> Result<String, Error> {
+    let userid = sqlx::query_scalar!("SELECT userid FROM login WHERE calendar_token = $1", token)
+        .fetch_optional(pool)
+        .await?
+        .ok_or(Error::Forbidden)?;
+    let events = calendar_events(pool, userid, None).await?;
+    Ok(calendar("NieletniPracuja interviews", &events))
+}
+
+/// Mails and notifies one side of an interview, then marks that side reminded
+async fn remind_interview(
+    pool: &Pool<Postgres>,
+    interviewid: i32,
+    userid: Uuid,
+    email: &str,
+    body: String,
+    company: bool,
+) -> Result<(), Error> {
+    send_mail(email, "Upcoming interview", body).await?;
+    sqlx::query!(
+        r#"UPDATE interviews SET
+            candidate_reminded = candidate_reminded OR NOT $2,
+            company_reminded = company_reminded OR $2
+        WHERE interviewid = $1"#,
+        interviewid,
+        company
+    )
+    .execute(pool)
+    .await?;
+    notify(pool, userid, Event::InterviewReminder { interviewid }).await;
+    Ok(())
+}
+
+/// Reminds both sides of accepted interviews starting within `REMINDER_BEFORE`, once per side.
+/// A side is marked reminded only once its mail is sent, failed ones are retried on the next check
+pub async fn send_interview_reminders(pool: &Pool<Postgres>) -> Result<(), Error> {
+    let now = OffsetDateTime::now_utc();
+    let interviews = sqlx::query!(
+        r#"SELECT
+            interviews.interviewid,
+            interviews.start_time AT TIME ZONE $3 "local_start!",
+            interviews.location,
+            interviews.candidate_reminded,
+            interviews.company_reminded,
+            jobs.job_location,
+            applications.userid,
+            users.email "user_email",
+            jobs.owner,
+            companies.email "company_email"
+        FROM interviews
+        JOIN applications ON applications.applicationid = interviews.applicationid
+        JOIN jobs ON jobs.jobid = applications.jobid
+        JOIN users ON users.userid = applications.userid
+        JOIN companies ON companies.userid = jobs.owner
+        WHERE interviews.status = 'accepted' AND
+            NOT (interviews.candidate_reminded AND interviews.company_reminded) AND
+            interviews.start_time > $1 AND
+            interviews.start_time <= $2"#,
+        now,
+        now + REMINDER_BEFORE,
+        TIME_ZONE
+    )
+    .fetch_all(pool)
+    .await?;
+
+    for interview in interviews {
+        let body = format!(
+            "Interview for {} on {} ({}), at: {}\n",
+            interview.job_location,
+            interview.local_start.format(LOCAL_TIME).unwrap_or_default(),
+            TIME_ZONE,
+            interview.location
+        );
+        for (userid, email, company, reminded) in [
+            (
+                interview.userid,
+                &interview.user_email,
+                false,
+                interview.candidate_reminded,
+            ),
+            (
+                interview.owner,
+                &interview.company_email,
+                true,
+                interview.company_reminded,
+            ),
+        ] {
+            if reminded {
+                continue;
+            }
+            let sent = remind_interview(
+                pool,
+                interview.interviewid,
+                userid,
+                email,
+                body.clone(),
+                company,
+            )
+            .await;
+            if let Err(e) = sent {
+                println!(
+                    "Interview reminder error for user {} of interview {}: {}",
+                    userid, interview.interviewid, e
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn reminder_task(pool: Pool<Postgres>) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Err(e) = send_interview_reminders(&pool).await {
+            println!("Interview reminder error: {}", e);
+        }
+    }
+}
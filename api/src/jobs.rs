@@ -1,7 +1,63 @@
 use serde::{Deserialize, Serialize};
-use sqlx::{types::JsonValue, Pool, Postgres};
+use sqlx::postgres::{PgHasArrayType, PgTypeInfo};
+use sqlx::{types::JsonValue, PgConnection, Pool, Postgres};
+use uuid::Uuid;
 
-#[derive(Serialize, Deserialize, Debug, sqlx::Type, Clone)]
+use crate::companies::CompanySummary;
+use crate::error::Error;
+use crate::expiry::expiry_time;
+use crate::locations::{distance_km, find_locality, parse_address};
+use crate::tags::{canonical_names, link_job_tags, resolve_tags, tag_strings, validate_tags};
+
+/// Used for `JobQuery::near` when `within_km` is not given
+const DEFAULT_RADIUS_KM: f64 = 10.0;
+
+/// Center as (latitude, longitude) and radius in km
+type Area = ((f64, f64), f64);
+
+/// Number of values listed in the tags and locations facets
+const TOP_TAGS: i64 = 20;
+const TOP_LOCATIONS: i64 = 20;
+
+/// Statutory minimum hourly rate for `Zlecenie` contracts in grosze,
+/// each applying from the given year and month
+const MINIMUM_HOURLY_RATES: [(i32, u8, i64); 4] = [
+    (2024, 1, 2770),
+    (2024, 7, 2810),
+    (2025, 1, 3050),
+    (2026, 1, 3140),
+];
+
+pub fn minimum_hourly_rate(date: time::Date) -> i64 {
+    MINIMUM_HOURLY_RATES
+        .iter()
+        .rev()
+        .find(|(year, month, _)| (date.year(), date.month() as u8) >= (*year, *month))
+        .unwrap_or(&MINIMUM_HOURLY_RATES[0])
+        .2
+}
+
+pub(crate) fn validate_pay(
+    contract_type: &ContractType,
+    amount: i64,
+    unit: &PayUnit,
+    currency: &str,
+) -> Result<(), Error> {
+    if amount <= 0 || currency.len() != 3 || !currency.chars().all(|c| c.is_ascii_uppercase()) {
+        return Err(Error::ImproperPay);
+    }
+    // Minors are exempt from PIT and ZUS as students, so net rates are compared as well
+    if *contract_type == ContractType::Zlecenie
+        && *unit == PayUnit::Hour
+        && currency == "PLN"
+        && amount < minimum_hourly_rate(time::OffsetDateTime::now_utc().date())
+    {
+        return Err(Error::PayBelowMinimum);
+    }
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize, Debug, sqlx::Type, Clone, PartialEq)]
 #[sqlx(type_name = "contract", rename_all = "lowercase")]
 pub enum ContractType {
     Praca,
@@ -9,16 +65,18 @@ pub enum ContractType {
     Zlecenie,
     Tmp,
 }
-#[derive(Serialize, Deserialize, Debug, sqlx::Type, Clone)]
+#[derive(Serialize, Deserialize, Debug, sqlx::Type, Clone, PartialEq)]
 #[sqlx(type_name = "job_hours", rename_all = "lowercase")]
 pub enum JobHours {
     Weekend,
     Holiday,
     Week,
     Elastic,
+    /// Dated shifts candidates sign up for one by one
+    Shifts,
 }
 
-#[derive(Serialize, Deserialize, Debug, sqlx::Type, Clone)]
+#[derive(Serialize, Deserialize, Debug, sqlx::Type, Clone, PartialEq)]
 #[sqlx(type_name = "job_mode", rename_all = "lowercase")]
 pub enum JobMode {
     Stationary,
@@ -27,6 +85,35 @@ pub enum JobMode {
     Mobile,
 }
 
+// Arrays of the enums are bound as multi-value filters in `JobQuery`
+impl PgHasArrayType for ContractType {
+    fn array_type_info() -> PgTypeInfo {
+        PgTypeInfo::with_name("_contract")
+    }
+}
+impl PgHasArrayType for JobHours {
+    fn array_type_info() -> PgTypeInfo {
+        PgTypeInfo::with_name("_job_hours")
+    }
+}
+impl PgHasArrayType for JobMode {
+    fn array_type_info() -> PgTypeInfo {
+        PgTypeInfo::with_name("_job_mode")
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, sqlx::Type, Clone, PartialEq)]
+#[sqlx(type_name = "pay_unit", rename_all = "lowercase")]
+pub enum PayUnit {
+    Hour,
+    Task,
+    Month,
+}
+
+pub(crate) fn default_currency() -> String {
+    "PLN".to_owned()
+}
+
 #[derive(Deserialize, Debug, Serialize)]
 pub struct JobCreateRequest {
     pub(crate) job_location: String,
@@ -34,22 +121,185 @@ pub struct JobCreateRequest {
     pub(crate) mode: JobMode,
     pub(crate) hours: JobHours,
     pub(crate) description: String,
-    pub(crate) tags: JsonValue,
+    pub(crate) tags: Vec<String>,
+    /// In the smallest unit of the currency, e.g. grosze. The pay is optional,
+    /// but when given the amount, unit and `pay_gross` all have to be set
+    #[serde(default)]
+    pub(crate) pay_amount: Option<i64>,
+    #[serde(default)]
+    pub(crate) pay_unit: Option<PayUnit>,
+    /// Defaults to PLN when the pay is given
+    #[serde(default)]
+    pub(crate) pay_currency: Option<String>,
+    #[serde(default)]
+    pub(crate) pay_gross: Option<bool>,
+    /// Defaults to `expiry::DEFAULT_LIFETIME` from now
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub(crate) expires_at: Option<time::OffsetDateTime>,
+}
+impl JobCreateRequest {
+    pub fn validate(&self) -> Result<(), Error> {
+        validate_tags(&self.tags)?;
+        match (self.pay_amount, &self.pay_unit, self.pay_gross) {
+            (None, None, None) if self.pay_currency.is_none() => Ok(()),
+            (Some(amount), Some(unit), Some(_)) => validate_pay(
+                &self.contract_type,
+                amount,
+                unit,
+                self.pay_currency.as_deref().unwrap_or("PLN"),
+            ),
+            _ => Err(Error::ImproperPay),
+        }
+    }
+    /// The validated job to insert with `add_job`
+    pub fn into_job(self, owner: Uuid) -> Result<Job, Error> {
+        self.validate()?;
+        let expires_at = expiry_time(self.expires_at)?;
+        let (locality, postal_code) = parse_address(&self.job_location);
+        Ok(Job {
+            owner,
+            jobid: -1,
+            creation_time: time::OffsetDateTime::now_utc(),
+            job_location: Some(self.job_location),
+            contract_type: self.contract_type,
+            mode: self.mode,
+            hours: self.hours,
+            description: Some(self.description),
+            tags: Some(self.tags.into()),
+            pay_currency: self
+                .pay_amount
+                .map(|_| self.pay_currency.unwrap_or_else(default_currency)),
+            pay_amount: self.pay_amount,
+            pay_unit: self.pay_unit,
+            pay_gross: self.pay_gross,
+            city: locality.map(|l| l.name.to_owned()),
+            voivodeship: locality.map(|l| l.voivodeship.to_owned()),
+            postal_code,
+            latitude: locality.map(|l| l.latitude),
+            longitude: locality.map(|l| l.longitude),
+            expires_at,
+            archived_at: None,
+        })
+    }
+}
+
+/// Either a single value or a list of accepted values, an empty list accepts everything
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(untagged)]
+pub enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+impl<T: Clone + PartialEq> OneOrMany<T> {
+    fn accepts(&self, value: &T) -> bool {
+        match self {
+            OneOrMany::One(v) => v == value,
+            OneOrMany::Many(v) => v.is_empty() || v.contains(value),
+        }
+    }
+}
+
+fn to_vec<T: Clone>(filter: &Option<OneOrMany<T>>) -> Vec<T> {
+    match filter {
+        None => Vec::new(),
+        Some(OneOrMany::One(v)) => vec![v.clone()],
+        Some(OneOrMany::Many(v)) => v.clone(),
+    }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 #[allow(unused)]
 pub struct JobQuery {
     job_location: Option<String>,
-    contract_type: Option<ContractType>,
-    mode: Option<JobMode>,
-    hours: Option<JobHours>,
+    contract_type: Option<OneOrMany<ContractType>>,
+    mode: Option<OneOrMany<JobMode>>,
+    hours: Option<OneOrMany<JobHours>>,
+    /// Jobs have to have all of these tags
     tags: Vec<String>,
+    /// Jobs have to have at least one of these tags, unless empty
+    #[serde(default)]
+    any_tags: Vec<String>,
+    /// Jobs with any of these tags are left out
+    #[serde(default)]
+    exclude_tags: Vec<String>,
     text: String,
+    /// In the smallest unit of `pay_currency`, jobs without a pay are left out when set.
+    /// Rates of different units aren't comparable, so it requires `pay_unit`
+    #[serde(default)]
+    min_pay: Option<i64>,
+    #[serde(default)]
+    pay_unit: Option<PayUnit>,
+    /// Only jobs paid in this currency, PLN when `min_pay` is set
+    #[serde(default)]
+    pay_currency: Option<String>,
+    /// Name of a locality, only jobs within `within_km` of it are returned
+    #[serde(default)]
+    near: Option<String>,
+    #[serde(default)]
+    within_km: Option<f64>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    created_after: Option<time::OffsetDateTime>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    created_before: Option<time::OffsetDateTime>,
+    /// Also count the results by facet, not part of the filters
+    #[serde(default, skip_serializing)]
+    pub(crate) facets: bool,
 }
 impl JobQuery {
-    pub async fn get_result(&self, pool: &Pool<Postgres>) -> Result<Vec<Job>, sqlx::Error> {
-        sqlx::query_as!(
+    /// Coordinates of `near` and the radius around them
+    fn get_area(&self) -> Result<Option<Area>, Error> {
+        let near = match &self.near {
+            Some(n) => n,
+            None => return Ok(None),
+        };
+        match find_locality(near) {
+            Some(l) => Ok(Some((
+                (l.latitude, l.longitude),
+                self.within_km.unwrap_or(DEFAULT_RADIUS_KM),
+            ))),
+            None => Err(Error::UnknownLocation),
+        }
+    }
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.min_pay.is_some() && self.pay_unit.is_none() {
+            return Err(Error::ImproperPayFilter);
+        }
+        Ok(())
+    }
+    /// The currency jobs are filtered by
+    fn currency(&self) -> Option<String> {
+        match &self.pay_currency {
+            Some(c) => Some(c.clone()),
+            None => self.min_pay.map(|_| default_currency()),
+        }
+    }
+    /// The same query with tags replaced by their canonical names
+    pub async fn canonical(&self, pool: &Pool<Postgres>) -> Result<JobQuery, sqlx::Error> {
+        let mut query = self.clone();
+        query.tags = canonical_names(pool, &self.tags).await?;
+        query.any_tags = canonical_names(pool, &self.any_tags).await?;
+        query.exclude_tags = canonical_names(pool, &self.exclude_tags).await?;
+        Ok(query)
+    }
+    /// Tags, locality and area the filters refer to
+    async fn resolve(&self, pool: &Pool<Postgres>) -> Result<ResolvedQuery, Error> {
+        self.validate()?;
+        Ok(ResolvedQuery {
+            tags: canonical_names(pool, &self.tags).await?,
+            any_tags: canonical_names(pool, &self.any_tags).await?,
+            exclude_tags: canonical_names(pool, &self.exclude_tags).await?,
+            city: self
+                .job_location
+                .as_deref()
+                .and_then(find_locality)
+                .map(|l| l.name.to_owned()),
+            area: self.get_area()?,
+        })
+    }
+    pub async fn get_result(&self, pool: &Pool<Postgres>) -> Result<Vec<Job>, Error> {
+        let resolved = self.resolve(pool).await?;
+        let area = resolved.area;
+        Ok(sqlx::query_as!(
             Job,
             r#"SELECT 
                 jobid,
@@ -60,27 +310,259 @@ impl JobQuery {
                 mode "mode: JobMode",
                 hours "hours: JobHours",
                 description,
-                tags 
+                tags,
+                pay_amount,
+                pay_unit "pay_unit: PayUnit",
+                pay_currency,
+                pay_gross,
+                city,
+                voivodeship,
+                postal_code,
+                latitude,
+                longitude,
+                expires_at,
+                archived_at
             FROM jobs WHERE
+                archived_at IS NULL AND
+                expires_at > now() AND
                 tags ?& $1 AND
-                ($2::text Is NULL OR job_location = $2::text) AND
-                ($3::contract Is NULL OR contract_type = $3::contract) AND
-                ($4::job_mode Is NULL OR mode = $4::job_mode) AND
-                ($5::job_hours Is NULL OR hours = $5::job_hours) AND
-                description like $6
+                (cardinality($13::text[]) = 0 OR tags ?| $13) AND
+                NOT tags ?| $14 AND
+                ($2::text Is NULL OR job_location = $2::text OR city = $9::text) AND
+                (cardinality($3::contract[]) = 0 OR contract_type = ANY($3)) AND
+                (cardinality($4::job_mode[]) = 0 OR mode = ANY($4)) AND
+                (cardinality($5::job_hours[]) = 0 OR hours = ANY($5)) AND
+                description like $6 AND
+                ($15::timestamptz Is NULL OR creation_time >= $15) AND
+                ($16::timestamptz Is NULL OR creation_time < $16) AND
+                ($7::bigint Is NULL OR pay_amount >= $7::bigint) AND
+                ($8::pay_unit Is NULL OR pay_unit = $8::pay_unit) AND
+                ($17::text Is NULL OR pay_currency = $17::text) AND
+                ($10::float8 Is NULL OR 2 * 6371 * asin(sqrt(
+                    power(sin(radians(latitude - $10::float8) / 2), 2) +
+                    cos(radians($10::float8)) * cos(radians(latitude)) *
+                    power(sin(radians(longitude - $11::float8) / 2), 2)
+                )) <= $12::float8)
             "#,
-            self.tags.as_slice(),
+            resolved.tags.as_slice(),
+            &self.job_location as &Option<String>,
+            to_vec(&self.contract_type) as Vec<ContractType>,
+            to_vec(&self.mode) as Vec<JobMode>,
+            to_vec(&self.hours) as Vec<JobHours>,
+            "%".to_owned() + &self.text + "%",
+            self.min_pay,
+            &self.pay_unit as &Option<_>,
+            resolved.city,
+            area.map(|a| a.0 .0),
+            area.map(|a| a.0 .1),
+            area.map(|a| a.1),
+            resolved.any_tags.as_slice(),
+            resolved.exclude_tags.as_slice(),
+            self.created_after,
+            self.created_before,
+            self.currency()
+        )
+        .fetch_all(pool)
+        .await?)
+    }
+    /// Number of jobs per value of each facet, counted with every filter except the facet's own,
+    /// so that picking another value of the facet gives the shown number of jobs.
+    /// All facets come from a single query
+    pub async fn get_facets(&self, pool: &Pool<Postgres>) -> Result<Facets, Error> {
+        let resolved = self.resolve(pool).await?;
+        let area = resolved.area;
+        let rows = sqlx::query!(
+            r#"WITH matched AS (
+                SELECT
+                    jobid,
+                    contract_type,
+                    mode,
+                    hours,
+                    city,
+                    (tags ?& $1 AND
+                        (cardinality($13::text[]) = 0 OR tags ?| $13) AND
+                        NOT tags ?| $14
+                    ) AS by_tags,
+                    ($2::text Is NULL OR job_location = $2::text OR city = $9::text) AS by_location,
+                    (cardinality($3::contract[]) = 0 OR contract_type = ANY($3)) AS by_contract_type,
+                    (cardinality($4::job_mode[]) = 0 OR mode = ANY($4)) AS by_mode,
+                    (cardinality($5::job_hours[]) = 0 OR hours = ANY($5)) AS by_hours,
+                    (description like $6 AND
+                        ($15::timestamptz Is NULL OR creation_time >= $15) AND
+                        ($16::timestamptz Is NULL OR creation_time < $16) AND
+                        ($7::bigint Is NULL OR pay_amount >= $7::bigint) AND
+                        ($8::pay_unit Is NULL OR pay_unit = $8::pay_unit) AND
+                        ($19::text Is NULL OR pay_currency = $19::text) AND
+                        ($10::float8 Is NULL OR 2 * 6371 * asin(sqrt(
+                            power(sin(radians(latitude - $10::float8) / 2), 2) +
+                            cos(radians($10::float8)) * cos(radians(latitude)) *
+                            power(sin(radians(longitude - $11::float8) / 2), 2)
+                        )) <= $12::float8)
+                    ) AS by_rest
+                FROM jobs
+                WHERE archived_at IS NULL AND expires_at > now()
+            )
+            (SELECT
+                'contract_type' "facet!",
+                contract_type "contract_type?: ContractType",
+                NULL::job_mode "mode?: JobMode",
+                NULL::job_hours "hours?: JobHours",
+                NULL::text "value?",
+                count(*) "count!"
+            FROM matched
+            WHERE by_tags AND by_location AND by_mode AND by_hours AND by_rest
+            GROUP BY contract_type
+            ORDER BY count(*) DESC)
+            UNION ALL
+            (SELECT 'mode', NULL, mode, NULL, NULL, count(*)
+            FROM matched
+            WHERE by_tags AND by_location AND by_contract_type AND by_hours AND by_rest
+            GROUP BY mode
+            ORDER BY count(*) DESC)
+            UNION ALL
+            (SELECT 'hours', NULL, NULL, hours, NULL, count(*)
+            FROM matched
+            WHERE by_tags AND by_location AND by_contract_type AND by_mode AND by_rest
+            GROUP BY hours
+            ORDER BY count(*) DESC)
+            UNION ALL
+            (SELECT 'tags', NULL, NULL, NULL, tags.name, count(*)
+            FROM matched
+            JOIN job_tags ON job_tags.jobid = matched.jobid
+            JOIN tags ON tags.tagid = job_tags.tagid
+            WHERE by_location AND by_contract_type AND by_mode AND by_hours AND by_rest
+            GROUP BY tags.name
+            ORDER BY count(*) DESC, tags.name
+            LIMIT $17)
+            UNION ALL
+            (SELECT 'locations', NULL, NULL, NULL, city, count(*)
+            FROM matched
+            WHERE city IS NOT NULL AND
+                by_tags AND by_contract_type AND by_mode AND by_hours AND by_rest
+            GROUP BY city
+            ORDER BY count(*) DESC, city
+            LIMIT $18)"#,
+            resolved.tags.as_slice(),
             &self.job_location as &Option<String>,
-            &self.contract_type as &Option<_>,
-            &self.mode as &Option<_>,
-            &self.hours as &Option<_>,
-            "%".to_owned() + &self.text + "%"
+            to_vec(&self.contract_type) as Vec<ContractType>,
+            to_vec(&self.mode) as Vec<JobMode>,
+            to_vec(&self.hours) as Vec<JobHours>,
+            "%".to_owned() + &self.text + "%",
+            self.min_pay,
+            &self.pay_unit as &Option<_>,
+            resolved.city,
+            area.map(|a| a.0 .0),
+            area.map(|a| a.0 .1),
+            area.map(|a| a.1),
+            resolved.any_tags.as_slice(),
+            resolved.exclude_tags.as_slice(),
+            self.created_after,
+            self.created_before,
+            TOP_TAGS,
+            TOP_LOCATIONS,
+            self.currency()
         )
         .fetch_all(pool)
-        .await
+        .await?;
+
+        let mut facets = Facets::default();
+        for row in rows {
+            let count = row.count;
+            match (
+                row.facet.as_str(),
+                row.contract_type,
+                row.mode,
+                row.hours,
+                row.value,
+            ) {
+                ("contract_type", Some(value), _, _, _) => {
+                    facets.contract_type.push(FacetCount { value, count })
+                }
+                ("mode", _, Some(value), _, _) => facets.mode.push(FacetCount { value, count }),
+                ("hours", _, _, Some(value), _) => facets.hours.push(FacetCount { value, count }),
+                ("tags", _, _, _, Some(value)) => facets.tags.push(FacetCount { value, count }),
+                ("locations", _, _, _, Some(value)) => {
+                    facets.locations.push(FacetCount { value, count })
+                }
+                _ => {}
+            }
+        }
+        Ok(facets)
+    }
+    /// Same conditions as `get_result`, checked against a single job without the database,
+    /// tags have to be made `canonical` first
+    pub fn matches(&self, job: &Job) -> bool {
+        let tags = job.tags.as_ref().and_then(|t| t.as_array());
+        let has_tag =
+            |tag: &String| tags.is_some_and(|t| t.iter().any(|v| v.as_str() == Some(tag)));
+        self.tags.iter().all(has_tag)
+            && (self.any_tags.is_empty() || self.any_tags.iter().any(has_tag))
+            && !self.exclude_tags.iter().any(has_tag)
+            && self.job_location.as_ref().is_none_or(|l| {
+                job.job_location.as_ref() == Some(l)
+                    || find_locality(l).is_some_and(|c| job.city.as_ref() == Some(&c.name))
+            })
+            && self
+                .contract_type
+                .as_ref()
+                .is_none_or(|c| c.accepts(&job.contract_type))
+            && self.mode.as_ref().is_none_or(|m| m.accepts(&job.mode))
+            && self.hours.as_ref().is_none_or(|h| h.accepts(&job.hours))
+            && self.created_after.is_none_or(|t| job.creation_time >= t)
+            && self.created_before.is_none_or(|t| job.creation_time < t)
+            && job.archived_at.is_none()
+            && job.expires_at > time::OffsetDateTime::now_utc()
+            && job
+                .description
+                .as_ref()
+                .is_some_and(|d| d.contains(&self.text))
+            && self
+                .min_pay
+                .is_none_or(|min| job.pay_amount.is_some_and(|p| p >= min))
+            && self
+                .pay_unit
+                .as_ref()
+                .is_none_or(|u| job.pay_unit.as_ref() == Some(u))
+            && self
+                .currency()
+                .is_none_or(|c| job.pay_currency.as_ref() == Some(&c))
+            && self.validate().is_ok()
+            && match self.get_area() {
+                Ok(None) => true,
+                Ok(Some((center, radius))) => job
+                    .latitude
+                    .zip(job.longitude)
+                    .is_some_and(|point| distance_km(center, point) <= radius),
+                Err(_) => false,
+            }
     }
 }
 
+struct ResolvedQuery {
+    tags: Vec<String>,
+    any_tags: Vec<String>,
+    exclude_tags: Vec<String>,
+    city: Option<String>,
+    area: Option<Area>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FacetCount<T> {
+    value: T,
+    count: i64,
+}
+
+#[derive(Debug, Serialize, Default)]
+pub struct Facets {
+    contract_type: Vec<FacetCount<ContractType>>,
+    mode: Vec<FacetCount<JobMode>>,
+    hours: Vec<FacetCount<JobHours>>,
+    /// Most used tags only, see `TOP_TAGS`
+    tags: Vec<FacetCount<String>>,
+    /// Localities recognised in job addresses, most common first
+    locations: Vec<FacetCount<String>>,
+}
+
 #[derive(Debug, sqlx::FromRow, Serialize, Deserialize, Clone)]
 #[allow(unused)]
 pub struct Job {
@@ -93,6 +575,39 @@ pub struct Job {
     pub(crate) hours: JobHours,
     pub(crate) description: Option<String>,
     pub(crate) tags: Option<JsonValue>,
+    pub(crate) pay_amount: Option<i64>,
+    pub(crate) pay_unit: Option<PayUnit>,
+    pub(crate) pay_currency: Option<String>,
+    pub(crate) pay_gross: Option<bool>,
+    pub(crate) city: Option<String>,
+    pub(crate) voivodeship: Option<String>,
+    pub(crate) postal_code: Option<String>,
+    pub(crate) latitude: Option<f64>,
+    pub(crate) longitude: Option<f64>,
+    pub(crate) expires_at: time::OffsetDateTime,
+    /// Set once the job expired, archived jobs are left out of searches
+    pub(crate) archived_at: Option<time::OffsetDateTime>,
+}
+
+/// A job as returned by searches, with details depending on who is asking
+#[derive(Debug, Serialize)]
+pub struct JobListing {
+    #[serde(flatten)]
+    pub(crate) job: Job,
+    /// Only present when the request carries a valid JWT
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) saved: Option<bool>,
+    /// Net pay per `pay_unit` for a candidate under 18
+    pub(crate) estimated_net: Option<i64>,
+    /// The company behind the job, with its rating
+    pub(crate) company: Option<CompanySummary>,
+}
+
+/// Response of `/api/get_jobs` when facets are asked for
+#[derive(Debug, Serialize)]
+pub struct JobSearchResult {
+    pub(crate) jobs: Vec<JobListing>,
+    pub(crate) facets: Facets,
 }
 
 pub async fn get_all_jobs(pool: &Pool<Postgres>) -> Result<Vec<Job>, sqlx::Error> {
@@ -106,19 +621,77 @@ pub async fn get_all_jobs(pool: &Pool<Postgres>) -> Result<Vec<Job>, sqlx::Error
         mode \"mode: JobMode\",
         hours \"hours: JobHours\",
         description,
-        tags 
+        tags,
+        pay_amount,
+        pay_unit \"pay_unit: PayUnit\",
+        pay_currency,
+        pay_gross,
+        city,
+        voivodeship,
+        postal_code,
+        latitude,
+        longitude,
+        expires_at,
+        archived_at
         FROM jobs"
     )
     .fetch_all(pool)
     .await
 }
 
-pub async fn add_job(pool: &Pool<Postgres>, job: &Job) -> Result<(), sqlx::Error> {
-    let added_jobs = sqlx::query_as!(
+pub async fn get_job(pool: &Pool<Postgres>, jobid: i32) -> Result<Option<Job>, sqlx::Error> {
+    sqlx::query_as!(
+        Job,
+        r#"SELECT
+            jobid,
+            owner,
+            creation_time,
+            job_location,
+            contract_type "contract_type: ContractType",
+            mode "mode: JobMode",
+            hours "hours: JobHours",
+            description,
+            tags,
+            pay_amount,
+            pay_unit "pay_unit: PayUnit",
+            pay_currency,
+            pay_gross,
+            city,
+            voivodeship,
+            postal_code,
+            latitude,
+            longitude,
+            expires_at,
+            archived_at
+        FROM jobs
+        WHERE jobid = $1"#,
+        jobid
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Inserts the job with its tags replaced by the canonical ones
+pub async fn add_job(pool: &Pool<Postgres>, job: &Job) -> Result<Job, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let added = insert_job(pool, &mut tx, job).await?;
+    tx.commit().await?;
+    Ok(added)
+}
+
+/// `add_job` within a transaction, new tags are added to the vocabulary outside of it
+pub async fn insert_job(
+    pool: &Pool<Postgres>,
+    tx: &mut PgConnection,
+    job: &Job,
+) -> Result<Job, sqlx::Error> {
+    let tags = resolve_tags(pool, &tag_strings(&job.tags)).await?;
+    let names: Vec<String> = tags.iter().map(|t| t.name.to_owned()).collect();
+    let mut added_jobs = sqlx::query_as!(
         Job,
-        "INSERT INTO jobs (owner, creation_time, job_location, contract_type, mode, hours, description, tags)
-        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
-        RETURNING jobid, owner, creation_time, job_location, contract_type \"contract_type: ContractType\", mode \"mode: JobMode\", hours \"hours: JobHours\", description, tags",
+        "INSERT INTO jobs (owner, creation_time, job_location, contract_type, mode, hours, description, tags, pay_amount, pay_unit, pay_currency, pay_gross, city, voivodeship, postal_code, latitude, longitude, expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18)
+        RETURNING jobid, owner, creation_time, job_location, contract_type \"contract_type: ContractType\", mode \"mode: JobMode\", hours \"hours: JobHours\", description, tags, pay_amount, pay_unit \"pay_unit: PayUnit\", pay_currency, pay_gross, city, voivodeship, postal_code, latitude, longitude, expires_at, archived_at",
         job.owner,
         job.creation_time,
         job.job_location,
@@ -126,8 +699,20 @@ pub async fn add_job(pool: &Pool<Postgres>, job: &Job) -> Result<(), sqlx::Error
         job.mode as _,
         job.hours as _,
         job.description,
-        job.tags
-    ).fetch_all(pool).await?;
+        JsonValue::from(names),
+        job.pay_amount,
+        job.pay_unit as _,
+        job.pay_currency,
+        job.pay_gross,
+        job.city,
+        job.voivodeship,
+        job.postal_code,
+        job.latitude,
+        job.longitude,
+        job.expires_at
+    ).fetch_all(&mut *tx).await?;
     assert_eq!(added_jobs.len(), 1);
-    Ok(())
+    let added = added_jobs.remove(0);
+    link_job_tags(&mut *tx, added.jobid, &tags).await?;
+    Ok(added)
 }
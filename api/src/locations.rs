@@ -0,0 +1,120 @@
+use lazy_static::lazy_static;
+use serde::Serialize;
+
+/// Offline list of Polish cities and larger towns, one `name,voivodeship,latitude,longitude` per line.
+/// It is not a full gazetteer, jobs in localities missing from it keep their location as free
+/// text, without a city or coordinates
+const LOCALITIES_CSV: &str = include_str!("../data/localities.csv");
+const EARTH_RADIUS_KM: f64 = 6371.0;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Locality {
+    pub(crate) name: String,
+    pub(crate) voivodeship: String,
+    pub(crate) latitude: f64,
+    pub(crate) longitude: f64,
+    #[serde(skip)]
+    folded: String,
+}
+
+fn parse_locality(line: &str) -> Option<Locality> {
+    let mut fields = line.split(',');
+    let name = fields.next()?.trim().to_owned();
+    let voivodeship = fields.next()?.trim().to_owned();
+    let latitude = fields.next()?.trim().parse().ok()?;
+    let longitude = fields.next()?.trim().parse().ok()?;
+    Some(Locality {
+        folded: fold(&name),
+        name,
+        voivodeship,
+        latitude,
+        longitude,
+    })
+}
+
+lazy_static! {
+    pub static ref LOCALITIES: Vec<Locality> = LOCALITIES_CSV
+        .lines()
+        .skip(1)
+        .filter_map(parse_locality)
+        .collect();
+}
+
+/// Lowercase, without Polish diacritics and repeated whitespace, so "Kraków" equals "krakow"
+pub fn fold(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+        .chars()
+        .map(|c| match c {
+            'ą' => 'a',
+            'ć' => 'c',
+            'ę' => 'e',
+            'ł' => 'l',
+            'ń' => 'n',
+            'ó' => 'o',
+            'ś' => 's',
+            'ź' | 'ż' => 'z',
+            c => c,
+        })
+        .collect()
+}
+
+pub fn find_locality(name: &str) -> Option<&'static Locality> {
+    let folded = fold(name);
+    LOCALITIES.iter().find(|l| l.folded == folded)
+}
+
+fn find_postal_code(address: &str) -> Option<String> {
+    let chars: Vec<char> = address.chars().collect();
+    chars
+        .windows(6)
+        .enumerate()
+        .find(|(i, w)| {
+            w[..2].iter().all(|c| c.is_ascii_digit())
+                && w[2] == '-'
+                && w[3..].iter().all(|c| c.is_ascii_digit())
+                && (*i == 0 || !chars[i - 1].is_ascii_digit())
+                && chars.get(i + 6).is_none_or(|c| !c.is_ascii_digit())
+        })
+        .map(|(_, w)| w.iter().collect())
+}
+
+/// Whether `name` appears in `text` as whole words
+fn contains_words(text: &str, name: &str) -> bool {
+    text.match_indices(name).any(|(i, _)| {
+        let before = text[..i].chars().next_back();
+        let after = text[i + name.len()..].chars().next();
+        before.is_none_or(|c| !c.is_alphanumeric()) && after.is_none_or(|c| !c.is_alphanumeric())
+    })
+}
+
+/// Picks the locality and the postal code out of a free form address like "ul. Długa 5, 31-147 Kraków"
+pub fn parse_address(address: &str) -> (Option<&'static Locality>, Option<String>) {
+    let postal_code = find_postal_code(address);
+    let by_part = address.split(',').find_map(|part| {
+        let part = match &postal_code {
+            Some(code) => part.replace(code.as_str(), ""),
+            None => part.to_owned(),
+        };
+        find_locality(&part)
+    });
+    let locality = by_part.or_else(|| {
+        let folded = fold(address);
+        LOCALITIES
+            .iter()
+            .filter(|l| contains_words(&folded, &l.folded))
+            .max_by_key(|l| l.folded.len())
+    });
+    (locality, postal_code)
+}
+
+/// Great-circle distance between two points given in degrees
+pub fn distance_km(from: (f64, f64), to: (f64, f64)) -> f64 {
+    let (lat1, lon1) = (from.0.to_radians(), from.1.to_radians());
+    let (lat2, lon2) = (to.0.to_radians(), to.1.to_radians());
+    let a = ((lat2 - lat1) / 2.0).sin().powi(2)
+        + lat1.cos() * lat2.cos() * ((lon2 - lon1) / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+}
@@ -0,0 +1,161 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+use crate::applications::Application;
+use crate::error::Error;
+use crate::notifications::{notify, Event};
+use crate::users::guardian_ward;
+
+#[derive(Debug, Deserialize)]
+pub struct SendMessageRequest {
+    applicationid: i32,
+    content: String,
+}
+impl SendMessageRequest {
+    pub async fn execute(&self, pool: &Pool<Postgres>, sender: Uuid) -> Result<Message, Error> {
+        if self.content.trim().is_empty() {
+            return Err(Error::EmptyMessage);
+        }
+        if !Application::is_participant(pool, self.applicationid, sender).await? {
+            return Err(Error::Forbidden);
+        }
+        let message = sqlx::query_as!(
+            Message,
+            r#"INSERT INTO messages (applicationid, sender, content, creation_time)
+            VALUES ($1, $2, $3, $4)
+            RETURNING messageid, applicationid, sender, content, creation_time, read_time"#,
+            self.applicationid,
+            sender,
+            self.content,
+            time::OffsetDateTime::now_utc()
+        )
+        .fetch_one(pool)
+        .await?;
+
+        let (applicant, owner) = Application::get_parties(pool, self.applicationid).await?;
+        let recipient = if sender == applicant {
+            owner
+        } else {
+            applicant
+        };
+        notify(
+            pool,
+            recipient,
+            Event::NewMessage {
+                applicationid: message.applicationid,
+                messageid: message.messageid,
+            },
+        )
+        .await;
+        Ok(message)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ThreadRequest {
+    applicationid: i32,
+}
+impl ThreadRequest {
+    pub async fn get_messages(
+        &self,
+        pool: &Pool<Postgres>,
+        userid: Uuid,
+    ) -> Result<Vec<Message>, Error> {
+        if !Application::is_participant(pool, self.applicationid, userid).await? {
+            return Err(Error::Forbidden);
+        }
+        Ok(sqlx::query_as!(
+            Message,
+            r#"SELECT messageid, applicationid, sender, content, creation_time, read_time
+            FROM messages
+            WHERE applicationid = $1
+            ORDER BY creation_time"#,
+            self.applicationid
+        )
+        .fetch_all(pool)
+        .await?)
+    }
+    /// Marks every message sent to the user in this thread as read, returns how many were updated
+    pub async fn mark_read(&self, pool: &Pool<Postgres>, userid: Uuid) -> Result<u64, Error> {
+        if !Application::is_participant(pool, self.applicationid, userid).await? {
+            return Err(Error::Forbidden);
+        }
+        Ok(sqlx::query!(
+            r#"UPDATE messages SET read_time = $3
+            WHERE applicationid = $1 AND sender <> $2 AND read_time IS NULL"#,
+            self.applicationid,
+            userid,
+            time::OffsetDateTime::now_utc()
+        )
+        .execute(pool)
+        .await?
+        .rows_affected())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GuardianThreadsRequest {
+    /// From the mail sent when the guardian was set, guardians have no accounts
+    token: Uuid,
+}
+impl GuardianThreadsRequest {
+    /// Messages of every application of the guardian's ward, read only, so nothing is marked read
+    pub async fn execute(&self, pool: &Pool<Postgres>) -> Result<Vec<Message>, Error> {
+        let userid = guardian_ward(pool, self.token).await?;
+        Ok(sqlx::query_as!(
+            Message,
+            r#"SELECT
+                messages.messageid,
+                messages.applicationid,
+                messages.sender,
+                messages.content,
+                messages.creation_time,
+                messages.read_time
+            FROM messages
+            JOIN applications ON applications.applicationid = messages.applicationid
+            WHERE applications.userid = $1
+            ORDER BY messages.applicationid, messages.creation_time"#,
+            userid
+        )
+        .fetch_all(pool)
+        .await?)
+    }
+}
+
+#[derive(Debug, Clone, sqlx::FromRow, Serialize, Deserialize)]
+pub struct Message {
+    pub(crate) messageid: i32,
+    pub(crate) applicationid: i32,
+    pub(crate) sender: Uuid,
+    pub(crate) content: String,
+    pub(crate) creation_time: time::OffsetDateTime,
+    pub(crate) read_time: Option<time::OffsetDateTime>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UnreadCount {
+    applicationid: i32,
+    unread: i64,
+}
+
+/// Unread messages per thread, only threads with at least one unread message are returned
+pub async fn get_unread_counts(
+    pool: &Pool<Postgres>,
+    userid: Uuid,
+) -> Result<Vec<UnreadCount>, sqlx::Error> {
+    sqlx::query_as!(
+        UnreadCount,
+        r#"SELECT messages.applicationid, count(*) "unread!"
+        FROM messages
+        JOIN applications ON applications.applicationid = messages.applicationid
+        JOIN jobs ON jobs.jobid = applications.jobid
+        WHERE (applications.userid = $1 OR jobs.owner = $1) AND
+            messages.sender <> $1 AND
+            messages.read_time IS NULL
+        GROUP BY messages.applicationid"#,
+        userid
+    )
+    .fetch_all(pool)
+    .await
+}
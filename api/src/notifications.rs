@@ -0,0 +1,165 @@
+use std::{collections::HashMap, convert::Infallible, sync::Arc};
+
+use futures_util::{SinkExt, Stream, StreamExt};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use sqlx::{postgres::PgListener, Pool, Postgres};
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::BroadcastStream;
+use uuid::Uuid;
+use warp::filters::ws::{Message, WebSocket};
+
+use crate::error::Error;
+
+/// Postgres channel every API instance listens on
+const CHANNEL: &str = "events";
+/// How many events a slow client may fall behind before it starts missing them
+const CAPACITY: usize = 64;
+const RECONNECT_DELAY: std::time::Duration = std::time::Duration::from_secs(5);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    NewMessage { applicationid: i32, messageid: i32 },
+    NewApplication { applicationid: i32, jobid: i32 },
+    JobAlert { searchid: i32, jobid: i32 },
+    JobExpiring { jobid: i32 },
+    JobArchived { jobid: i32 },
+    DraftPublished { draftid: i32, jobid: i32 },
+    DraftFailed { draftid: i32 },
+    ShiftSignup { shiftid: i32, jobid: i32 },
+    ShiftWithdrawal { shiftid: i32, jobid: i32 },
+    ShiftCancelled { shiftid: i32, jobid: i32 },
+    InterviewProposed { applicationid: i32 },
+    InterviewAccepted { interviewid: i32 },
+    InterviewDeclined { interviewid: i32 },
+    InterviewCancelled { interviewid: i32 },
+    InterviewReminder { interviewid: i32 },
+    Hired { applicationid: i32, contractid: i32 },
+    OfferReceived { offerid: i32 },
+    OfferAccepted { offerid: i32 },
+    TimesheetSubmitted { entryid: i32 },
+    TimesheetReviewed { entryid: i32 },
+    EngagementCompleted { applicationid: i32 },
+    ReviewReceived { reviewid: i32 },
+    InvitedToApply { jobid: i32 },
+}
+/// Payload sent through `pg_notify`, only ids are carried to stay under the payload limit
+#[derive(Debug, Serialize, Deserialize)]
+struct Notification {
+    userid: Uuid,
+    event: Event,
+}
+
+async fn publish(pool: &Pool<Postgres>, userid: Uuid, event: Event) -> Result<(), Error> {
+    let payload = serde_json::to_string(&Notification { userid, event })?;
+    sqlx::query!("SELECT pg_notify($1, $2)", CHANNEL, payload)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Sends the event to the user through Postgres, so that it reaches clients connected to any instance.
+/// Events follow changes that are already saved, so a failure is only logged
+pub async fn notify(pool: &Pool<Postgres>, userid: Uuid, event: Event) {
+    if let Err(e) = publish(pool, userid, event).await {
+        println!("Notification error: {}", e);
+    }
+}
+
+/// Per-user channels of the clients connected to this instance
+#[derive(Clone, Default)]
+pub struct Notifier {
+    channels: Arc<RwLock<HashMap<Uuid, broadcast::Sender<Event>>>>,
+}
+impl Notifier {
+    pub fn subscribe(&self, userid: Uuid) -> broadcast::Receiver<Event> {
+        let mut channels = self.channels.write();
+        channels.retain(|_, sender| sender.receiver_count() > 0);
+        channels
+            .entry(userid)
+            .or_insert_with(|| broadcast::channel(CAPACITY).0)
+            .subscribe()
+    }
+    fn dispatch(&self, notification: Notification) {
+        if let Some(sender) = self.channels.read().get(&notification.userid) {
+            // Nobody listening is not an error, the client just disconnected
+            let _ = sender.send(notification.event);
+        }
+    }
+    /// Forwards notifications from Postgres to the connected clients, never returns.
+    /// Connecting is retried until it succeeds, afterwards the listener reconnects by itself
+    pub async fn listen(self, pool: Pool<Postgres>) {
+        let mut listener = loop {
+            match connect_listener(&pool).await {
+                Ok(l) => break l,
+                Err(e) => {
+                    println!("Notification listener error: {}", e);
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                }
+            }
+        };
+        loop {
+            let notification = match listener.recv().await {
+                Ok(n) => n,
+                Err(e) => {
+                    println!("Notification listener error: {}", e);
+                    // Each recv retries the connection, so wait while Postgres is down
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                    continue;
+                }
+            };
+            match serde_json::from_str::<Notification>(notification.payload()) {
+                Ok(n) => self.dispatch(n),
+                Err(e) => println!("Bad notification payload: {}", e),
+            }
+        }
+    }
+}
+
+async fn connect_listener(pool: &Pool<Postgres>) -> Result<PgListener, sqlx::Error> {
+    let mut listener = PgListener::connect_with(pool).await?;
+    listener.listen(CHANNEL).await?;
+    Ok(listener)
+}
+
+pub async fn event_socket(socket: WebSocket, mut events: broadcast::Receiver<Event>) {
+    let (mut tx, mut rx) = socket.split();
+    loop {
+        tokio::select! {
+            event = events.recv() => {
+                let event = match event {
+                    Ok(e) => e,
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                let text = match serde_json::to_string(&event) {
+                    Ok(t) => t,
+                    Err(_) => continue,
+                };
+                if tx.send(Message::text(text)).await.is_err() {
+                    break;
+                }
+            }
+            incoming = rx.next() => match incoming {
+                Some(Ok(message)) if !message.is_close() => continue,
+                _ => break,
+            }
+        }
+    }
+}
+
+pub fn event_stream(
+    events: broadcast::Receiver<Event>,
+) -> impl Stream<Item = Result<warp::sse::Event, Infallible>> {
+    BroadcastStream::new(events).filter_map(|event| async move {
+        // Named by the same `type` the WebSocket messages carry
+        let data = serde_json::to_value(event.ok()?).ok()?;
+        let name = data["type"].as_str()?.to_owned();
+        warp::sse::Event::default()
+            .event(name)
+            .json_data(&data)
+            .ok()
+            .map(Ok)
+    })
+}
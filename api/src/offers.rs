@@ -0,0 +1,722 @@
+use std::net::IpAddr;
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Postgres};
+use time::{Date, OffsetDateTime};
+use uuid::Uuid;
+
+use crate::applications::Application;
+use crate::contracts::{contract_name, pay_text, POLISH_DATE};
+use crate::error::Error;
+use crate::jobs::{default_currency, validate_pay, ContractType, PayUnit};
+use crate::mail::{send_mail, APP_URL};
+use crate::notifications::{notify, Event};
+use crate::pdf::text_document;
+use crate::shifts::TIME_ZONE;
+use crate::users::{age_on, iso_date, ADULT_AGE};
+
+/// Hex SHA-256 of the exact bytes of a document
+pub fn sha256_hex(document: &[u8]) -> String {
+    Sha256::digest(document)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+lazy_static! {
+    /// Comma separated addresses of the reverse proxies whose `X-Forwarded-For` is trusted
+    static ref TRUSTED_PROXIES: Vec<IpAddr> = dotenv::var("TRUSTED_PROXIES")
+        .map(|proxies| proxies.split(',').filter_map(|p| p.trim().parse().ok()).collect())
+        .unwrap_or_default();
+}
+
+/// Where an acceptance came from, recorded in the audit trail
+#[derive(Debug, Clone)]
+pub struct Client {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+impl Client {
+    /// The address of the peer. `X-Forwarded-For` is only read when the peer is one of
+    /// `TRUSTED_PROXIES`, then the nearest entry not added by a trusted proxy is taken
+    pub fn new(
+        remote: Option<IpAddr>,
+        forwarded: Option<&str>,
+        user_agent: Option<String>,
+    ) -> Client {
+        let mut ip = remote;
+        if remote.is_some_and(|r| TRUSTED_PROXIES.contains(&r)) {
+            for hop in forwarded.unwrap_or_default().rsplit(',') {
+                match hop.trim().parse::<IpAddr>() {
+                    Ok(hop) => {
+                        ip = Some(hop);
+                        if !TRUSTED_PROXIES.contains(&hop) {
+                            break;
+                        }
+                    }
+                    Err(_) => break,
+                }
+            }
+        }
+        Client {
+            ip: ip.map(|ip| ip.to_string()),
+            user_agent,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, sqlx::Type, Clone, Copy, PartialEq)]
+#[sqlx(type_name = "offer_party", rename_all = "lowercase")]
+pub enum OfferParty {
+    Candidate,
+    /// The statutory representative co-accepting for a minor
+    Guardian,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Offer {
+    offerid: i32,
+    applicationid: i32,
+    contract_type: ContractType,
+    pay_amount: i64,
+    pay_unit: PayUnit,
+    pay_currency: String,
+    pay_gross: bool,
+    #[serde(with = "iso_date")]
+    start_date: Date,
+    terms: String,
+    document_hash: String,
+    creation_time: OffsetDateTime,
+    /// Set once the candidate, and the guardian of a minor, accepted
+    accepted_at: Option<OffsetDateTime>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateOfferRequest {
+    applicationid: i32,
+    contract_type: ContractType,
+    pay_amount: i64,
+    pay_unit: PayUnit,
+    #[serde(default = "default_currency")]
+    pay_currency: String,
+    pay_gross: bool,
+    #[serde(with = "iso_date")]
+    start_date: Date,
+    #[serde(default)]
+    terms: String,
+}
+impl CreateOfferRequest {
+    /// Generates the offer document the candidate, and the guardian of a minor, accept
+    pub async fn execute(&self, pool: &Pool<Postgres>, owner: Uuid) -> Result<Offer, Error> {
+        let parties = sqlx::query!(
+            r#"SELECT
+                applications.jobid,
+                applications.userid,
+                users.full_name,
+                companies.company_name,
+                companies.nip,
+                jobs.owner,
+                jobs.job_location,
+                (now() AT TIME ZONE $2)::date "today!"
+            FROM applications
+            JOIN users ON users.userid = applications.userid
+            JOIN jobs ON jobs.jobid = applications.jobid
+            JOIN companies ON companies.userid = jobs.owner
+            WHERE applications.applicationid = $1"#,
+            self.applicationid,
+            TIME_ZONE
+        )
+        .fetch_optional(pool)
+        .await?
+        .filter(|p| p.owner == owner)
+        .ok_or(Error::Forbidden)?;
+        validate_pay(
+            &self.contract_type,
+            self.pay_amount,
+            &self.pay_unit,
+            &self.pay_currency,
+        )?;
+        if self.start_date < parties.today {
+            return Err(Error::ImproperOffer);
+        }
+
+        let date = |d: Date| d.format(POLISH_DATE).unwrap_or_default();
+        // The reference keeps two offers with the same terms from sharing a hash
+        let text = format!(
+            "Numer referencyjny: {}\n\
+            Data wystawienia: {}\n\
+            Pracodawca: {}, NIP {}\n\
+            Kandydat: {}\n\
+            Miejsce pracy: {}, ogłoszenie nr {}, aplikacja nr {}\n\
+            Rodzaj umowy: {}\n\
+            Wynagrodzenie: {}\n\
+            Data rozpoczęcia: {}\n\
+            \n\
+            Warunki:\n\
+            {}\n\
+            \n\
+            Oferta wymaga akceptacji kandydata, a w przypadku osoby niepełnoletniej także \
+            jej przedstawiciela ustawowego.",
+            Uuid::new_v4(),
+            date(parties.today),
+            parties.company_name,
+            parties.nip,
+            parties.full_name,
+            parties.job_location,
+            parties.jobid,
+            self.applicationid,
+            contract_name(&self.contract_type),
+            pay_text(
+                Some(self.pay_amount),
+                Some(self.pay_unit.clone()),
+                Some(self.pay_currency.clone()),
+                Some(self.pay_gross),
+            ),
+            date(self.start_date),
+            self.terms.trim()
+        );
+        let document = text_document("Oferta zatrudnienia", &text);
+        let offer = sqlx::query_as!(
+            Offer,
+            r#"INSERT INTO offers (
+                applicationid,
+                contract_type,
+                pay_amount,
+                pay_unit,
+                pay_currency,
+                pay_gross,
+                start_date,
+                terms,
+                document,
+                document_hash,
+                creation_time
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            RETURNING
+                offerid,
+                applicationid,
+                contract_type "contract_type: ContractType",
+                pay_amount,
+                pay_unit "pay_unit: PayUnit",
+                pay_currency,
+                pay_gross,
+                start_date,
+                terms,
+                document_hash,
+                creation_time,
+                accepted_at"#,
+            self.applicationid,
+            self.contract_type.clone() as _,
+            self.pay_amount,
+            self.pay_unit.clone() as _,
+            self.pay_currency,
+            self.pay_gross,
+            self.start_date,
+            self.terms.trim(),
+            &document,
+            sha256_hex(&document),
+            OffsetDateTime::now_utc()
+        )
+        .fetch_one(pool)
+        .await?;
+
+        notify(
+            pool,
+            parties.userid,
+            Event::OfferReceived {
+                offerid: offer.offerid,
+            },
+        )
+        .await;
+        Ok(offer)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OffersRequest {
+    applicationid: i32,
+}
+impl OffersRequest {
+    pub async fn execute(&self, pool: &Pool<Postgres>, userid: Uuid) -> Result<Vec<Offer>, Error> {
+        if !Application::is_participant(pool, self.applicationid, userid).await? {
+            return Err(Error::Forbidden);
+        }
+        Ok(sqlx::query_as!(
+            Offer,
+            r#"SELECT
+                offerid,
+                applicationid,
+                contract_type "contract_type: ContractType",
+                pay_amount,
+                pay_unit "pay_unit: PayUnit",
+                pay_currency,
+                pay_gross,
+                start_date,
+                terms,
+                document_hash,
+                creation_time,
+                accepted_at
+            FROM offers
+            WHERE applicationid = $1
+            ORDER BY creation_time DESC"#,
+            self.applicationid
+        )
+        .fetch_all(pool)
+        .await?)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Acceptance {
+    party: OfferParty,
+    name: String,
+    ip: Option<String>,
+    user_agent: Option<String>,
+    document_hash: String,
+    accepted_at: OffsetDateTime,
+}
+
+/// The audit trail of an offer
+#[derive(Debug, Serialize)]
+pub struct Certificate {
+    offerid: i32,
+    applicationid: i32,
+    document_hash: String,
+    creation_time: OffsetDateTime,
+    accepted_at: Option<OffsetDateTime>,
+    acceptances: Vec<Acceptance>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OfferRequest {
+    pub(crate) offerid: i32,
+}
+impl OfferRequest {
+    /// The offer PDF, for the candidate and the company
+    pub async fn document(&self, pool: &Pool<Postgres>, userid: Uuid) -> Result<Vec<u8>, Error> {
+        sqlx::query_scalar!(
+            r#"SELECT offers.document
+            FROM offers
+            JOIN applications ON applications.applicationid = offers.applicationid
+            JOIN jobs ON jobs.jobid = applications.jobid
+            WHERE offers.offerid = $1 AND
+                (applications.userid = $2 OR jobs.owner = $2)"#,
+            self.offerid,
+            userid
+        )
+        .fetch_optional(pool)
+        .await?
+        .ok_or(Error::Forbidden)
+    }
+    pub async fn certificate(
+        &self,
+        pool: &Pool<Postgres>,
+        userid: Uuid,
+    ) -> Result<Certificate, Error> {
+        let offer = sqlx::query!(
+            r#"SELECT
+                offers.applicationid,
+                offers.document_hash,
+                offers.creation_time,
+                offers.accepted_at
+            FROM offers
+            JOIN applications ON applications.applicationid = offers.applicationid
+            JOIN jobs ON jobs.jobid = applications.jobid
+            WHERE offers.offerid = $1 AND
+                (applications.userid = $2 OR jobs.owner = $2)"#,
+            self.offerid,
+            userid
+        )
+        .fetch_optional(pool)
+        .await?
+        .ok_or(Error::Forbidden)?;
+        let acceptances = sqlx::query_as!(
+            Acceptance,
+            r#"SELECT
+                party "party: OfferParty",
+                name,
+                ip,
+                user_agent,
+                document_hash,
+                accepted_at
+            FROM offer_acceptances
+            WHERE offerid = $1
+            ORDER BY accepted_at"#,
+            self.offerid
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(Certificate {
+            offerid: self.offerid,
+            applicationid: offer.applicationid,
+            document_hash: offer.document_hash,
+            creation_time: offer.creation_time,
+            accepted_at: offer.accepted_at,
+            acceptances,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AcceptOfferRequest {
+    offerid: i32,
+    /// Hash of the document the candidate was shown, it has to match the offer
+    document_hash: String,
+}
+impl AcceptOfferRequest {
+    /// Records the candidate's acceptance. For a minor the confirmed guardian is then mailed
+    /// a link to co-accept, otherwise the offer is accepted right away
+    pub async fn execute(
+        &self,
+        pool: &Pool<Postgres>,
+        userid: Uuid,
+        client: Client,
+    ) -> Result<(), Error> {
+        let mut tx = pool.begin().await?;
+        let offer = sqlx::query!(
+            r#"SELECT
+                offers.document,
+                offers.accepted_at,
+                EXISTS (
+                    SELECT 1 FROM offer_acceptances
+                    WHERE offer_acceptances.offerid = offers.offerid AND
+                        offer_acceptances.party = 'candidate'
+                ) "candidate_accepted!",
+                applications.userid,
+                users.full_name,
+                users.birth_date,
+                users.guardian_name,
+                users.guardian_email,
+                users.guardian_confirmed_at,
+                companies.company_name,
+                jobs.owner,
+                (now() AT TIME ZONE $2)::date "today!"
+            FROM offers
+            JOIN applications ON applications.applicationid = offers.applicationid
+            JOIN users ON users.userid = applications.userid
+            JOIN jobs ON jobs.jobid = applications.jobid
+            JOIN companies ON companies.userid = jobs.owner
+            WHERE offers.offerid = $1
+            FOR UPDATE OF offers"#,
+            self.offerid,
+            TIME_ZONE
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .filter(|o| o.userid == userid)
+        .ok_or(Error::Forbidden)?;
+        if offer.accepted_at.is_some() || offer.candidate_accepted {
+            return Err(Error::AlreadyAccepted);
+        }
+        let document_hash = sha256_hex(&offer.document);
+        if document_hash != self.document_hash.to_lowercase() {
+            return Err(Error::DocumentMismatch);
+        }
+        let adult = offer
+            .birth_date
+            .is_some_and(|b| age_on(b, offer.today) >= ADULT_AGE);
+        let guardian = match (adult, offer.guardian_name, offer.guardian_email) {
+            (true, _, _) => None,
+            (false, Some(name), Some(email)) if offer.guardian_confirmed_at.is_some() => {
+                Some((name, email))
+            }
+            (false, _, _) => return Err(Error::MissingGuardian),
+        };
+
+        let now = OffsetDateTime::now_utc();
+        sqlx::query!(
+            r#"INSERT INTO offer_acceptances
+                (offerid, party, name, ip, user_agent, document_hash, accepted_at)
+            VALUES ($1, 'candidate', $2, $3, $4, $5, $6)"#,
+            self.offerid,
+            offer.full_name,
+            client.ip,
+            client.user_agent,
+            document_hash,
+            now
+        )
+        .execute(&mut *tx)
+        .await?;
+        let token = guardian.as_ref().map(|_| Uuid::new_v4());
+        let (guardian_name, guardian_email) = guardian.unzip();
+        sqlx::query!(
+            r#"UPDATE offers SET
+                guardian_token = $2,
+                guardian_name = $3,
+                guardian_email = $4,
+                accepted_at = CASE WHEN $2::uuid IS NULL THEN $5::timestamptz END
+            WHERE offerid = $1"#,
+            self.offerid,
+            token,
+            guardian_name,
+            guardian_email,
+            now
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        match (guardian_email, token) {
+            (Some(email), Some(token)) => {
+                // The acceptance is already recorded, a lost mail is sent again with `resend`
+                let mailed =
+                    mail_guardian_offer(&email, &offer.full_name, &offer.company_name, token).await;
+                if let Err(e) = mailed {
+                    println!(
+                        "Guardian offer mail error for offer {}: {}",
+                        self.offerid, e
+                    );
+                }
+            }
+            _ => {
+                notify(
+                    pool,
+                    offer.owner,
+                    Event::OfferAccepted {
+                        offerid: self.offerid,
+                    },
+                )
+                .await;
+            }
+        }
+        Ok(())
+    }
+}
+
+async fn mail_guardian_offer(
+    email: &str,
+    candidate: &str,
+    company: &str,
+    token: Uuid,
+) -> Result<(), Error> {
+    send_mail(
+        email,
+        "Job offer awaiting your acceptance",
+        format!(
+            "{} accepted a job offer from {} and needs your consent as their guardian.\n\
+            Review and accept the offer at {}/guardian/offers/{}\n",
+            candidate, company, *APP_URL, token
+        ),
+    )
+    .await
+}
+
+impl OfferRequest {
+    /// Mails the current confirmed guardian a new link to an offer the candidate accepted,
+    /// the previous link stops working
+    pub async fn resend(&self, pool: &Pool<Postgres>, userid: Uuid) -> Result<(), Error> {
+        let offer = sqlx::query!(
+            r#"SELECT
+                offers.accepted_at,
+                EXISTS (
+                    SELECT 1 FROM offer_acceptances
+                    WHERE offer_acceptances.offerid = offers.offerid AND
+                        offer_acceptances.party = 'candidate'
+                ) "candidate_accepted!",
+                users.full_name,
+                users.guardian_name,
+                users.guardian_email,
+                users.guardian_confirmed_at,
+                companies.company_name
+            FROM offers
+            JOIN applications ON applications.applicationid = offers.applicationid
+            JOIN users ON users.userid = applications.userid
+            JOIN jobs ON jobs.jobid = applications.jobid
+            JOIN companies ON companies.userid = jobs.owner
+            WHERE offers.offerid = $1 AND applications.userid = $2"#,
+            self.offerid,
+            userid
+        )
+        .fetch_optional(pool)
+        .await?
+        .ok_or(Error::Forbidden)?;
+        if offer.accepted_at.is_some() || !offer.candidate_accepted {
+            return Err(Error::AlreadyAccepted);
+        }
+        let (name, email) = match (
+            offer.guardian_name,
+            offer.guardian_email,
+            offer.guardian_confirmed_at,
+        ) {
+            (Some(name), Some(email), Some(_)) => (name, email),
+            _ => return Err(Error::MissingGuardian),
+        };
+        let token = Uuid::new_v4();
+        sqlx::query!(
+            r#"UPDATE offers SET guardian_token = $2, guardian_name = $3, guardian_email = $4
+            WHERE offerid = $1 AND accepted_at IS NULL"#,
+            self.offerid,
+            token,
+            name,
+            email
+        )
+        .execute(pool)
+        .await?;
+        mail_guardian_offer(&email, &offer.full_name, &offer.company_name, token).await
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GuardianOfferRequest {
+    /// From the link mailed to the guardian, guardians have no accounts
+    token: Uuid,
+}
+impl GuardianOfferRequest {
+    pub async fn get(&self, pool: &Pool<Postgres>) -> Result<Offer, Error> {
+        sqlx::query_as!(
+            Offer,
+            r#"SELECT
+                offerid,
+                applicationid,
+                contract_type "contract_type: ContractType",
+                pay_amount,
+                pay_unit "pay_unit: PayUnit",
+                pay_currency,
+                pay_gross,
+                start_date,
+                terms,
+                document_hash,
+                creation_time,
+                accepted_at
+            FROM offers
+            WHERE guardian_token = $1"#,
+            self.token
+        )
+        .fetch_optional(pool)
+        .await?
+        .ok_or(Error::Forbidden)
+    }
+    pub async fn document(&self, pool: &Pool<Postgres>) -> Result<Vec<u8>, Error> {
+        sqlx::query_scalar!(
+            "SELECT document FROM offers WHERE guardian_token = $1",
+            self.token
+        )
+        .fetch_optional(pool)
+        .await?
+        .ok_or(Error::Forbidden)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GuardianAcceptRequest {
+    token: Uuid,
+    /// Hash of the document the guardian was shown, it has to match the offer
+    document_hash: String,
+}
+impl GuardianAcceptRequest {
+    pub async fn execute(&self, pool: &Pool<Postgres>, client: Client) -> Result<(), Error> {
+        let mut tx = pool.begin().await?;
+        let offer = sqlx::query!(
+            r#"SELECT
+                offers.offerid,
+                offers.document,
+                offers.accepted_at,
+                offers.guardian_name,
+                applications.userid,
+                jobs.owner
+            FROM offers
+            JOIN applications ON applications.applicationid = offers.applicationid
+            JOIN users ON users.userid = applications.userid
+            JOIN jobs ON jobs.jobid = applications.jobid
+            WHERE offers.guardian_token = $1 AND users.guardian_confirmed_at IS NOT NULL
+            FOR UPDATE OF offers"#,
+            self.token
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(Error::Forbidden)?;
+        if offer.accepted_at.is_some() {
+            return Err(Error::AlreadyAccepted);
+        }
+        let document_hash = sha256_hex(&offer.document);
+        if document_hash != self.document_hash.to_lowercase() {
+            return Err(Error::DocumentMismatch);
+        }
+
+        let now = OffsetDateTime::now_utc();
+        sqlx::query!(
+            r#"INSERT INTO offer_acceptances
+                (offerid, party, name, ip, user_agent, document_hash, accepted_at)
+            VALUES ($1, 'guardian', $2, $3, $4, $5, $6)"#,
+            offer.offerid,
+            offer.guardian_name.unwrap_or_default(),
+            client.ip,
+            client.user_agent,
+            document_hash,
+            now
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "UPDATE offers SET accepted_at = $2 WHERE offerid = $1",
+            offer.offerid,
+            now
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        for userid in [offer.owner, offer.userid] {
+            notify(
+                pool,
+                userid,
+                Event::OfferAccepted {
+                    offerid: offer.offerid,
+                },
+            )
+            .await;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct VerifiedAcceptance {
+    party: OfferParty,
+    accepted_at: OffsetDateTime,
+}
+
+/// What anyone holding the document may learn about it, without the personal data
+#[derive(Debug, Serialize)]
+pub struct Verification {
+    offerid: i32,
+    creation_time: OffsetDateTime,
+    accepted_at: Option<OffsetDateTime>,
+    acceptances: Vec<VerifiedAcceptance>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyRequest {
+    /// Hex SHA-256 of the PDF being verified
+    document_hash: String,
+}
+impl VerifyRequest {
+    pub async fn execute(&self, pool: &Pool<Postgres>) -> Result<Verification, Error> {
+        let offer = sqlx::query!(
+            "SELECT offerid, creation_time, accepted_at FROM offers WHERE document_hash = $1",
+            self.document_hash.to_lowercase()
+        )
+        .fetch_optional(pool)
+        .await?
+        .ok_or(Error::UnknownDocument)?;
+        let acceptances = sqlx::query_as!(
+            VerifiedAcceptance,
+            r#"SELECT party "party: OfferParty", accepted_at
+            FROM offer_acceptances
+            WHERE offerid = $1 AND document_hash = $2
+            ORDER BY accepted_at"#,
+            offer.offerid,
+            self.document_hash.to_lowercase()
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(Verification {
+            offerid: offer.offerid,
+            creation_time: offer.creation_time,
+            accepted_at: offer.accepted_at,
+            acceptances,
+        })
+    }
+}
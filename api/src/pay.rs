@@ -0,0 +1,206 @@
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::jobs::{ContractType, Job, PayUnit};
+
+// Contribution and tax rates of 2026 in basis points, ZUS contributions are taken from the gross amount
+const PENSION: i64 = 976;
+const DISABILITY_EMPLOYEE: i64 = 150;
+const DISABILITY_EMPLOYER: i64 = 650;
+const SICKNESS: i64 = 245;
+const ACCIDENT: i64 = 167;
+const LABOUR_FUND: i64 = 245;
+const GUARANTEED_BENEFITS_FUND: i64 = 10;
+const HEALTH: i64 = 900;
+const PIT: i64 = 1200;
+/// Flat deductible costs of `Zlecenie` and `Dzielo`
+const COSTS_RATE: i64 = 2000;
+
+/// Monthly deductible costs and tax reduction of employment contracts, in grosze
+const EMPLOYMENT_COSTS: i64 = 25_000;
+const TAX_REDUCTION: i64 = 30_000;
+/// `Dzielo` up to this amount is taxed at a flat rate without deductible costs
+const FLAT_TAX_LIMIT: i64 = 20_000;
+
+/// Below this age wages are exempt from PIT, and pupils and students from ZUS on `Zlecenie`
+const YOUTH_AGE: i32 = 26;
+/// Schooling is compulsory until this age, so minors are counted as pupils
+const PUPIL_AGE: i32 = 18;
+
+const MAX_HOURS: f64 = 744.0;
+/// Larger amounts, in grosze, are not estimated
+const MAX_AMOUNT: i64 = 1_000_000_000;
+
+fn share(amount: i64, rate: i64) -> i64 {
+    (amount * rate + 5_000) / 10_000
+}
+
+/// Taxes are rounded to full złoty
+fn round_zloty(amount: i64) -> i64 {
+    (amount + 50) / 100 * 100
+}
+
+/// Amounts in grosze
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct PayEstimate {
+    gross: i64,
+    /// Pension, disability and sickness contributions paid by the worker
+    employee_zus: i64,
+    health: i64,
+    tax: i64,
+    net: i64,
+    /// Gross amount with the contributions paid by the employer
+    employer_cost: i64,
+}
+
+/// Estimates the net pay of a monthly `gross` amount, the annual limit of the youth relief is
+/// not tracked and employment contracts are assumed to have the tax reduction applied
+pub fn estimate_pay(
+    contract_type: &ContractType,
+    gross: i64,
+    age: i32,
+    student: bool,
+) -> PayEstimate {
+    let young = age < YOUTH_AGE;
+    let employer_zus =
+        PENSION + DISABILITY_EMPLOYER + ACCIDENT + LABOUR_FUND + GUARANTEED_BENEFITS_FUND;
+    let (employee_zus, health, tax, employer_cost) = match contract_type {
+        ContractType::Praca | ContractType::Tmp => {
+            let zus = share(gross, PENSION + DISABILITY_EMPLOYEE + SICKNESS);
+            let health = share(gross - zus, HEALTH);
+            let tax = if young {
+                0
+            } else {
+                let base = round_zloty(gross - zus - EMPLOYMENT_COSTS).max(0);
+                round_zloty(share(base, PIT) - TAX_REDUCTION).max(0)
+            };
+            (zus, health, tax, gross + share(gross, employer_zus))
+        }
+        ContractType::Zlecenie if young && student => (0, 0, 0, gross),
+        ContractType::Zlecenie => {
+            // Sickness insurance is voluntary and left out
+            let zus = share(gross, PENSION + DISABILITY_EMPLOYEE);
+            let health = share(gross - zus, HEALTH);
+            let tax = if young {
+                0
+            } else {
+                let income = gross - zus - share(gross - zus, COSTS_RATE);
+                round_zloty(share(round_zloty(income), PIT))
+            };
+            (zus, health, tax, gross + share(gross, employer_zus))
+        }
+        // The youth relief doesn't cover `Dzielo`
+        ContractType::Dzielo if gross <= FLAT_TAX_LIMIT => {
+            (0, 0, round_zloty(share(gross, PIT)), gross)
+        }
+        ContractType::Dzielo => {
+            let income = gross - share(gross, COSTS_RATE);
+            (0, 0, round_zloty(share(round_zloty(income), PIT)), gross)
+        }
+    };
+    PayEstimate {
+        gross,
+        employee_zus,
+        health,
+        tax,
+        net: gross - employee_zus - health - tax,
+        employer_cost,
+    }
+}
+
+/// Net pay per `pay_unit` of the job for a candidate under 18, the audience of the listings.
+/// Only jobs paid in PLN are estimated
+pub fn listing_net(job: &Job) -> Option<i64> {
+    if job.pay_currency.as_deref() != Some("PLN") {
+        return None;
+    }
+    let amount = job.pay_amount.filter(|a| *a <= MAX_AMOUNT)?;
+    if job.pay_gross == Some(false) {
+        return Some(amount);
+    }
+    Some(estimate_pay(&job.contract_type, amount, PUPIL_AGE - 1, true).net)
+}
+
+/// The estimate for a worker of `age`, minors and workers of unknown age are counted as pupils
+pub fn estimate_for_age(contract_type: &ContractType, gross: i64, age: Option<i32>) -> PayEstimate {
+    let age = age.unwrap_or(PUPIL_AGE - 1);
+    estimate_pay(contract_type, gross, age, age < PUPIL_AGE)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PayCalculationRequest {
+    contract_type: ContractType,
+    /// In grosze
+    pay_amount: i64,
+    pay_unit: PayUnit,
+    /// Worked in the month, only used with `PayUnit::Hour`
+    #[serde(default)]
+    hours: Option<f64>,
+    age: i32,
+    /// Pupil or student, exempt from ZUS on `Zlecenie` until 26
+    #[serde(default)]
+    student: bool,
+}
+impl PayCalculationRequest {
+    pub fn execute(&self) -> Result<PayEstimate, Error> {
+        let gross = match (&self.pay_unit, self.hours) {
+            (PayUnit::Hour, Some(hours)) if hours > 0.0 && hours <= MAX_HOURS => {
+                (self.pay_amount as f64 * hours).round() as i64
+            }
+            (PayUnit::Hour, _) => return Err(Error::ImproperPay),
+            (_, _) => self.pay_amount,
+        };
+        if self.pay_amount <= 0 || gross > MAX_AMOUNT || !(13..=120).contains(&self.age) {
+            return Err(Error::ImproperPay);
+        }
+        Ok(estimate_pay(
+            &self.contract_type,
+            gross,
+            self.age,
+            self.student,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn employment_of_a_minor_is_exempt_from_tax() {
+        let estimate = estimate_pay(&ContractType::Praca, 400_000, 17, true);
+        assert_eq!(estimate.employee_zus, 54_840);
+        assert_eq!(estimate.health, 31_064);
+        assert_eq!(estimate.tax, 0);
+        assert_eq!(estimate.net, 314_096);
+        assert_eq!(estimate.employer_cost, 481_920);
+    }
+
+    #[test]
+    fn employment_of_an_adult_is_taxed() {
+        let estimate = estimate_pay(&ContractType::Praca, 400_000, 30, false);
+        assert_eq!(estimate.tax, 8_400);
+        assert_eq!(estimate.net, 400_000 - 54_840 - 31_064 - 8_400);
+    }
+
+    #[test]
+    fn zlecenie_of_a_student_is_paid_in_full() {
+        let estimate = estimate_pay(&ContractType::Zlecenie, 150_000, 19, true);
+        assert_eq!(estimate.net, 150_000);
+        assert_eq!(estimate.employer_cost, 150_000);
+
+        let estimate = estimate_pay(&ContractType::Zlecenie, 150_000, 19, false);
+        assert!(estimate.employee_zus > 0);
+        assert_eq!(estimate.tax, 0);
+    }
+
+    #[test]
+    fn small_dzielo_is_taxed_at_a_flat_rate() {
+        let estimate = estimate_pay(&ContractType::Dzielo, 20_000, 17, true);
+        assert_eq!(estimate.tax, 2_400);
+        assert_eq!(estimate.net, 17_600);
+
+        let estimate = estimate_pay(&ContractType::Dzielo, 100_000, 17, true);
+        assert_eq!(estimate.tax, 9_600);
+    }
+}
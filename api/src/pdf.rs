@@ -0,0 +1,154 @@
+use pdf_writer::{Content, Finish, Name, Pdf, Rect, Ref, Str};
+
+const PAGE_WIDTH: f32 = 595.0;
+const PAGE_HEIGHT: f32 = 842.0;
+const MARGIN: f32 = 56.0;
+const TITLE_SIZE: f32 = 14.0;
+const FONT_SIZE: f32 = 10.0;
+const LEADING: f32 = 14.0;
+
+const REGULAR: Name = Name(b"F1");
+const BOLD: Name = Name(b"F2");
+/// Polish letters missing from WinAnsiEncoding, remapped from this code on
+const EXTRA_CODE: u8 = 0x80;
+const EXTRA_CHARS: [(char, &[u8]); 17] = [
+    ('ą', b"aogonek"),
+    ('ć', b"cacute"),
+    ('ę', b"eogonek"),
+    ('ł', b"lslash"),
+    ('ń', b"nacute"),
+    ('ś', b"sacute"),
+    ('ź', b"zacute"),
+    ('ż', b"zdotaccent"),
+    ('Ą', b"Aogonek"),
+    ('Ć', b"Cacute"),
+    ('Ę', b"Eogonek"),
+    ('Ł', b"Lslash"),
+    ('Ń', b"Nacute"),
+    ('Ś', b"Sacute"),
+    ('Ź', b"Zacute"),
+    ('Ż', b"Zdotaccent"),
+    ('„', b"quotedblbase"),
+];
+
+/// The character in the font encoding, Latin-1 is shared with WinAnsiEncoding
+fn encode_char(c: char) -> u8 {
+    if let Some(i) = EXTRA_CHARS.iter().position(|(extra, _)| *extra == c) {
+        return EXTRA_CODE + i as u8;
+    }
+    match c {
+        '”' => 0x94,
+        '–' => 0x96,
+        '—' => 0x97,
+        ' '..='~' | '\u{a0}'..='\u{ff}' => c as u8,
+        _ => b'?',
+    }
+}
+
+/// Approximate Helvetica advance width in thousandths of the font size
+fn char_width(c: char) -> f32 {
+    match c {
+        'i' | 'j' | 'l' | '\'' | '|' => 222.0,
+        ' ' | '.' | ',' | ':' | ';' | '!' | 'f' | 't' | 'I' | '/' | '(' | ')' | '[' | ']' => 278.0,
+        'r' | '-' => 333.0,
+        'm' | 'M' | 'W' => 833.0,
+        'w' => 722.0,
+        c if c.is_uppercase() => 700.0,
+        _ => 556.0,
+    }
+}
+
+fn text_width(text: &str, size: f32) -> f32 {
+    text.chars().map(char_width).sum::<f32>() * size / 1000.0
+}
+
+/// Splits the paragraph into lines fitting `width`, breaking at spaces
+fn wrap(paragraph: &str, size: f32, width: f32) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in paragraph.split(' ') {
+        let candidate = if line.is_empty() {
+            word.to_owned()
+        } else {
+            format!("{} {}", line, word)
+        };
+        if !line.is_empty() && text_width(&candidate, size) > width {
+            lines.push(std::mem::replace(&mut line, word.to_owned()));
+        } else {
+            line = candidate;
+        }
+    }
+    lines.push(line);
+    lines
+}
+
+/// An A4 document with a bold title and the text below it, every line of `text` is a paragraph
+pub fn text_document(title: &str, text: &str) -> Vec<u8> {
+    let width = PAGE_WIDTH - 2.0 * MARGIN;
+    let mut lines: Vec<(Name, f32, String)> = wrap(title, TITLE_SIZE, width)
+        .into_iter()
+        .map(|l| (BOLD, TITLE_SIZE, l))
+        .collect();
+    lines.push((REGULAR, FONT_SIZE, String::new()));
+    for paragraph in text.lines() {
+        for line in wrap(paragraph.trim_end(), FONT_SIZE, width) {
+            lines.push((REGULAR, FONT_SIZE, line));
+        }
+    }
+    let per_page = ((PAGE_HEIGHT - 2.0 * MARGIN) / LEADING) as usize;
+    let pages: Vec<_> = lines.chunks(per_page).collect();
+
+    let mut pdf = Pdf::new();
+    let catalog_id = Ref::new(1);
+    let page_tree_id = Ref::new(2);
+    let regular_id = Ref::new(3);
+    let bold_id = Ref::new(4);
+    let page_ids: Vec<_> = (0..pages.len())
+        .map(|i| Ref::new(5 + 2 * i as i32))
+        .collect();
+
+    pdf.catalog(catalog_id).pages(page_tree_id);
+    pdf.pages(page_tree_id)
+        .kids(page_ids.iter().copied())
+        .count(pages.len() as i32);
+    for (font_id, base_font) in [(regular_id, "Helvetica"), (bold_id, "Helvetica-Bold")] {
+        let mut font = pdf.type1_font(font_id);
+        font.base_font(Name(base_font.as_bytes()));
+        let mut encoding = font.encoding_custom();
+        encoding.base_encoding(Name(b"WinAnsiEncoding"));
+        encoding
+            .differences()
+            .consecutive(EXTRA_CODE, EXTRA_CHARS.iter().map(|(_, name)| Name(name)));
+    }
+
+    for (page_id, page_lines) in page_ids.iter().zip(pages) {
+        let content_id = Ref::new(page_id.get() + 1);
+        let mut page = pdf.page(*page_id);
+        page.media_box(Rect::new(0.0, 0.0, PAGE_WIDTH, PAGE_HEIGHT));
+        page.parent(page_tree_id);
+        page.contents(content_id);
+        page.resources()
+            .fonts()
+            .pair(REGULAR, regular_id)
+            .pair(BOLD, bold_id);
+        page.finish();
+
+        let mut content = Content::new();
+        content.begin_text();
+        content.set_leading(LEADING);
+        content.next_line(MARGIN, PAGE_HEIGHT - MARGIN);
+        let mut current = None;
+        for (font, size, line) in page_lines {
+            if current != Some((*font, *size)) {
+                content.set_font(*font, *size);
+                current = Some((*font, *size));
+            }
+            let encoded: Vec<u8> = line.chars().map(encode_char).collect();
+            content.show(Str(&encoded));
+            content.next_line_using_leading();
+        }
+        content.end_text();
+        pdf.stream(content_id, &content.finish());
+    }
+    pdf.finish()
+}
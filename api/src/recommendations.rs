@@ -0,0 +1,230 @@
+use serde::Serialize;
+use sqlx::{Pool, Postgres};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::candidates::{get_candidate_profile, DEFAULT_TRAVEL_KM};
+use crate::contracts::old_enough;
+use crate::jobs::{ContractType, Job, JobHours, JobListing, JobMode, PayUnit};
+use crate::locations::{distance_km, find_locality};
+use crate::shifts::{check_juvenile_limits, declared_hours_allowed, TIME_ZONE};
+use crate::tags::tag_strings;
+
+/// Newest open jobs that are scored, older ones are not recommended
+const CANDIDATE_JOBS: i64 = 500;
+const RECOMMENDED_JOBS: usize = 20;
+
+// Score of each signal, the skill and history weights are per matching tag
+const SKILL_WEIGHT: f64 = 3.0;
+const HOURS_WEIGHT: f64 = 2.0;
+const MODE_WEIGHT: f64 = 1.5;
+/// Given in full at the candidate's city, falling to zero at the commuting distance
+const NEARBY_WEIGHT: f64 = 2.0;
+const HISTORY_WEIGHT: f64 = 1.0;
+/// History only nudges the order, so few of its tags count
+const MAX_HISTORY_TAGS: usize = 3;
+
+/// Why a job was recommended
+#[derive(Debug, Serialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum Reason {
+    /// Tags of the job among the skills of the candidate
+    Skills {
+        tags: Vec<String>,
+    },
+    /// The hours of the job fit the availability of the candidate
+    Availability {
+        hours: JobHours,
+    },
+    PreferredMode {
+        mode: JobMode,
+    },
+    Nearby {
+        distance_km: f64,
+    },
+    /// Tags shared with jobs the candidate applied to or saved
+    SimilarToPast {
+        tags: Vec<String>,
+    },
+}
+
+pub struct ScoredJob {
+    pub(crate) job: Job,
+    pub(crate) score: f64,
+    pub(crate) reasons: Vec<Reason>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Recommendation {
+    #[serde(flatten)]
+    pub(crate) listing: JobListing,
+    pub(crate) score: f64,
+    pub(crate) reasons: Vec<Reason>,
+}
+
+/// Open jobs the candidate has not applied to and may take, best first. Jobs beyond the
+/// commuting distance are left out, and so are those the candidate is too young for or whose
+/// hours break the limits for juvenile workers
+pub async fn recommend_jobs(
+    pool: &Pool<Postgres>,
+    userid: Uuid,
+) -> Result<Vec<ScoredJob>, sqlx::Error> {
+    let profile = get_candidate_profile(pool, userid).await?;
+    let birth_date = sqlx::query_scalar!("SELECT birth_date FROM users WHERE userid = $1", userid)
+        .fetch_one(pool)
+        .await?;
+    let history = sqlx::query_scalar!(
+        r#"SELECT tags
+        FROM jobs
+        WHERE jobid IN (
+            SELECT jobid FROM applications WHERE userid = $1
+            UNION
+            SELECT jobid FROM saved_jobs WHERE userid = $1
+        )"#,
+        userid
+    )
+    .fetch_all(pool)
+    .await?;
+    let history_tags: Vec<String> = history.iter().flat_map(tag_strings).collect();
+
+    let jobs = sqlx::query_as!(
+        Job,
+        r#"SELECT
+            jobid,
+            owner,
+            creation_time,
+            job_location,
+            contract_type "contract_type: ContractType",
+            mode "mode: JobMode",
+            hours "hours: JobHours",
+            description,
+            tags,
+            pay_amount,
+            pay_unit "pay_unit: PayUnit",
+            pay_currency,
+            pay_gross,
+            city,
+            voivodeship,
+            postal_code,
+            latitude,
+            longitude,
+            expires_at,
+            archived_at
+        FROM jobs
+        WHERE archived_at IS NULL AND
+            expires_at > now() AND
+            jobid NOT IN (SELECT jobid FROM applications WHERE userid = $1)
+        ORDER BY creation_time DESC
+        LIMIT $2"#,
+        userid,
+        CANDIDATE_JOBS
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let shift_jobs: Vec<i32> = jobs
+        .iter()
+        .filter(|j| j.hours == JobHours::Shifts)
+        .map(|j| j.jobid)
+        .collect();
+    let shifts = sqlx::query!(
+        r#"SELECT
+            jobid,
+            start_time AT TIME ZONE $2 "local_start!",
+            end_time AT TIME ZONE $2 "local_end!"
+        FROM shifts
+        WHERE jobid = ANY($1) AND
+            start_time > now() AND
+            (SELECT count(*) FROM shift_signups WHERE shift_signups.shiftid = shifts.shiftid) < capacity"#,
+        &shift_jobs,
+        TIME_ZONE
+    )
+    .fetch_all(pool)
+    .await?;
+    // Only the limits of a single shift, the candidate's other bookings are checked on sign-up
+    let workable: Vec<i32> = shifts
+        .into_iter()
+        .filter(|s| {
+            let length = s.local_end - s.local_start;
+            check_juvenile_limits(birth_date, &[], s.local_start, s.local_end, length).is_ok()
+        })
+        .map(|s| s.jobid)
+        .collect();
+
+    let home = profile
+        .city
+        .as_deref()
+        .and_then(find_locality)
+        .map(|l| (l.latitude, l.longitude));
+    let travel_km = profile.within_km.unwrap_or(DEFAULT_TRAVEL_KM);
+
+    let today = OffsetDateTime::now_utc().date();
+    let mut scored: Vec<ScoredJob> = Vec::new();
+    for job in jobs {
+        if !old_enough(birth_date, &job.contract_type, today)
+            || !declared_hours_allowed(birth_date, &job.hours, today)
+            || job.hours == JobHours::Shifts && !workable.contains(&job.jobid)
+        {
+            continue;
+        }
+        let distance = match (home, job.latitude, job.longitude) {
+            (Some(home), Some(lat), Some(lon)) if job.mode != JobMode::Home => {
+                Some(distance_km(home, (lat, lon)))
+            }
+            _ => None,
+        };
+        if distance.is_some_and(|d| d > travel_km) {
+            continue;
+        }
+
+        let tags = tag_strings(&job.tags);
+        let mut score = 0.0;
+        let mut reasons = Vec::new();
+        let skills: Vec<String> = tags
+            .iter()
+            .filter(|t| profile.skills.contains(t))
+            .cloned()
+            .collect();
+        if !skills.is_empty() {
+            score += SKILL_WEIGHT * skills.len() as f64;
+            reasons.push(Reason::Skills { tags: skills });
+        }
+        if profile.hours.contains(&job.hours) {
+            score += HOURS_WEIGHT;
+            reasons.push(Reason::Availability {
+                hours: job.hours.clone(),
+            });
+        }
+        if profile.modes.contains(&job.mode) {
+            score += MODE_WEIGHT;
+            reasons.push(Reason::PreferredMode {
+                mode: job.mode.clone(),
+            });
+        }
+        if let Some(d) = distance {
+            score += NEARBY_WEIGHT * (1.0 - d / travel_km);
+            reasons.push(Reason::Nearby {
+                distance_km: (d * 10.0).round() / 10.0,
+            });
+        }
+        let similar: Vec<String> = tags
+            .iter()
+            .filter(|t| history_tags.contains(t))
+            .take(MAX_HISTORY_TAGS)
+            .cloned()
+            .collect();
+        if !similar.is_empty() {
+            score += HISTORY_WEIGHT * similar.len() as f64;
+            reasons.push(Reason::SimilarToPast { tags: similar });
+        }
+        scored.push(ScoredJob {
+            job,
+            score,
+            reasons,
+        });
+    }
+    // Stable, so equally scored jobs stay newest first
+    scored.sort_by(|a, b| b.score.total_cmp(&a.score));
+    scored.truncate(RECOMMENDED_JOBS);
+    Ok(scored)
+}
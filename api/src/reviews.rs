@@ -0,0 +1,399 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::error::Error;
+use crate::notifications::{notify, Event};
+use crate::users::UserRole;
+
+/// Approved timesheet entries an engagement needs before it can be reviewed,
+/// so reviews come from work that actually happened
+const MIN_APPROVED_ENTRIES: i64 = 1;
+const MAX_TEXT_LENGTH: usize = 2000;
+
+#[derive(Serialize, Deserialize, Debug, sqlx::Type, Clone, Copy, PartialEq)]
+#[sqlx(type_name = "review_author", rename_all = "lowercase")]
+pub enum ReviewAuthor {
+    Candidate,
+    Guardian,
+    Company,
+}
+
+#[derive(Serialize, Deserialize, Debug, sqlx::Type, Clone, Copy, PartialEq)]
+#[sqlx(type_name = "review_status", rename_all = "lowercase")]
+pub enum ReviewStatus {
+    Pending,
+    Approved,
+    Rejected,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Review {
+    reviewid: i32,
+    applicationid: i32,
+    author: ReviewAuthor,
+    rating: i16,
+    comment: String,
+    status: ReviewStatus,
+    creation_time: OffsetDateTime,
+    reply: Option<String>,
+    replied_at: Option<OffsetDateTime>,
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct RatingSummary {
+    /// None without approved reviews
+    pub(crate) average: Option<f64>,
+    pub(crate) count: i64,
+}
+
+/// Aggregates of the approved reviews about companies or candidates, one rating per
+/// application, the candidate's rather than their guardian's. Subjects without approved
+/// reviews are left out
+pub async fn rating_summaries(
+    pool: &Pool<Postgres>,
+    subjects: &[Uuid],
+) -> Result<HashMap<Uuid, RatingSummary>, sqlx::Error> {
+    let ratings = sqlx::query!(
+        r#"SELECT subject, avg(rating)::float8 average, count(*) "count!"
+        FROM (
+            SELECT DISTINCT ON (applicationid, subject) subject, rating
+            FROM reviews
+            WHERE subject = ANY($1) AND status = 'approved'
+            ORDER BY applicationid, subject, author
+        ) rated
+        GROUP BY subject"#,
+        subjects
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(ratings
+        .into_iter()
+        .map(|r| {
+            let summary = RatingSummary {
+                average: r.average,
+                count: r.count,
+            };
+            (r.subject, summary)
+        })
+        .collect())
+}
+
+/// Aggregate of the approved reviews about a company or a candidate
+pub async fn rating_summary(
+    pool: &Pool<Postgres>,
+    subject: Uuid,
+) -> Result<RatingSummary, sqlx::Error> {
+    Ok(rating_summaries(pool, &[subject])
+        .await?
+        .remove(&subject)
+        .unwrap_or_default())
+}
+
+#[derive(Debug, Serialize)]
+pub struct Reviews {
+    summary: RatingSummary,
+    reviews: Vec<Review>,
+}
+
+async fn approved_reviews(pool: &Pool<Postgres>, subject: Uuid) -> Result<Reviews, sqlx::Error> {
+    let reviews = sqlx::query_as!(
+        Review,
+        r#"SELECT
+            reviewid,
+            applicationid,
+            author "author: ReviewAuthor",
+            rating,
+            comment,
+            status "status: ReviewStatus",
+            creation_time,
+            reply,
+            replied_at
+        FROM reviews
+        WHERE subject = $1 AND status = 'approved'
+        ORDER BY creation_time DESC"#,
+        subject
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(Reviews {
+        summary: rating_summary(pool, subject).await?,
+        reviews,
+    })
+}
+
+fn validate_review(rating: i16, comment: &str) -> Result<(), Error> {
+    if !(1..=5).contains(&rating) || comment.chars().count() > MAX_TEXT_LENGTH {
+        return Err(Error::ImproperReview);
+    }
+    Ok(())
+}
+
+/// Inserts a pending review after checking the engagement ended with approved work
+async fn add_review(
+    pool: &Pool<Postgres>,
+    applicationid: i32,
+    author: ReviewAuthor,
+    subject: Uuid,
+    rating: i16,
+    comment: &str,
+) -> Result<Review, Error> {
+    validate_review(rating, comment)?;
+    let engagement = sqlx::query!(
+        r#"SELECT
+            applications.completed_at,
+            (SELECT count(*)
+            FROM timesheet_entries
+            WHERE timesheet_entries.applicationid = applications.applicationid AND
+                timesheet_entries.status = 'approved') "approved_entries!"
+        FROM applications
+        WHERE applications.applicationid = $1"#,
+        applicationid
+    )
+    .fetch_one(pool)
+    .await?;
+    if engagement.completed_at.is_none() {
+        return Err(Error::NotCompleted);
+    }
+    if engagement.approved_entries < MIN_APPROVED_ENTRIES {
+        return Err(Error::ReviewNotAllowed);
+    }
+    sqlx::query_as!(
+        Review,
+        r#"INSERT INTO reviews (applicationid, author, subject, rating, comment, status, creation_time)
+        VALUES ($1, $2, $3, $4, $5, 'pending', $6)
+        ON CONFLICT (applicationid, author) DO NOTHING
+        RETURNING
+            reviewid,
+            applicationid,
+            author "author: ReviewAuthor",
+            rating,
+            comment,
+            status "status: ReviewStatus",
+            creation_time,
+            reply,
+            replied_at"#,
+        applicationid,
+        author as _,
+        subject,
+        rating,
+        comment.trim(),
+        OffsetDateTime::now_utc()
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or(Error::AlreadyReviewed)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WriteReviewRequest {
+    applicationid: i32,
+    /// 1 to 5
+    rating: i16,
+    #[serde(default)]
+    comment: String,
+}
+impl WriteReviewRequest {
+    /// The candidate reviews the company, the company reviews the candidate
+    pub async fn execute(&self, pool: &Pool<Postgres>, userid: Uuid) -> Result<Review, Error> {
+        let parties = sqlx::query!(
+            r#"SELECT applications.userid, jobs.owner
+            FROM applications
+            JOIN jobs ON jobs.jobid = applications.jobid
+            WHERE applications.applicationid = $1"#,
+            self.applicationid
+        )
+        .fetch_optional(pool)
+        .await?
+        .ok_or(Error::Forbidden)?;
+        let (author, subject) = if parties.userid == userid {
+            (ReviewAuthor::Candidate, parties.owner)
+        } else if parties.owner == userid {
+            (ReviewAuthor::Company, parties.userid)
+        } else {
+            return Err(Error::Forbidden);
+        };
+        add_review(
+            pool,
+            self.applicationid,
+            author,
+            subject,
+            self.rating,
+            &self.comment,
+        )
+        .await
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GuardianReviewRequest {
+    /// From the mail sent when the guardian was set, guardians have no accounts
+    token: Uuid,
+    applicationid: i32,
+    rating: i16,
+    #[serde(default)]
+    comment: String,
+}
+impl GuardianReviewRequest {
+    /// The guardian of the candidate reviews the company
+    pub async fn execute(&self, pool: &Pool<Postgres>) -> Result<Review, Error> {
+        let owner = sqlx::query_scalar!(
+            r#"SELECT jobs.owner
+            FROM applications
+            JOIN users ON users.userid = applications.userid
+            JOIN jobs ON jobs.jobid = applications.jobid
+            WHERE applications.applicationid = $1 AND
+                users.guardian_token = $2 AND
+                users.guardian_confirmed_at IS NOT NULL"#,
+            self.applicationid,
+            self.token
+        )
+        .fetch_optional(pool)
+        .await?
+        .ok_or(Error::Forbidden)?;
+        add_review(
+            pool,
+            self.applicationid,
+            ReviewAuthor::Guardian,
+            owner,
+            self.rating,
+            &self.comment,
+        )
+        .await
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReviewsRequest {
+    userid: Uuid,
+}
+impl ReviewsRequest {
+    /// Reviews about a company, public
+    pub async fn company(&self, pool: &Pool<Postgres>) -> Result<Reviews, Error> {
+        let company = sqlx::query_scalar!(
+            "SELECT userid FROM companies WHERE userid = $1",
+            self.userid
+        )
+        .fetch_optional(pool)
+        .await?;
+        if company.is_none() {
+            return Err(Error::Forbidden);
+        }
+        Ok(approved_reviews(pool, self.userid).await?)
+    }
+    /// Reviews about a candidate, for companies and the candidate
+    pub async fn candidate(
+        &self,
+        pool: &Pool<Postgres>,
+        viewer: Uuid,
+        role: &UserRole,
+    ) -> Result<Reviews, Error> {
+        if *role != UserRole::Company && viewer != self.userid {
+            return Err(Error::Forbidden);
+        }
+        Ok(approved_reviews(pool, self.userid).await?)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReplyRequest {
+    reviewid: i32,
+    reply: String,
+}
+impl ReplyRequest {
+    /// The company answers an approved review about it, once
+    pub async fn execute(&self, pool: &Pool<Postgres>, owner: Uuid) -> Result<(), Error> {
+        let reply = self.reply.trim();
+        if reply.is_empty() || reply.chars().count() > MAX_TEXT_LENGTH {
+            return Err(Error::ImproperReview);
+        }
+        let review = sqlx::query!(
+            r#"SELECT status "status: ReviewStatus", author "author: ReviewAuthor", reply
+            FROM reviews
+            WHERE reviewid = $1 AND subject = $2"#,
+            self.reviewid,
+            owner
+        )
+        .fetch_optional(pool)
+        .await?
+        .filter(|r| r.status == ReviewStatus::Approved && r.author != ReviewAuthor::Company)
+        .ok_or(Error::Forbidden)?;
+        if review.reply.is_some() {
+            return Err(Error::AlreadyReplied);
+        }
+        let replied = sqlx::query!(
+            "UPDATE reviews SET reply = $2, replied_at = $3 WHERE reviewid = $1 AND reply IS NULL",
+            self.reviewid,
+            reply,
+            OffsetDateTime::now_utc()
+        )
+        .execute(pool)
+        .await?;
+        if replied.rows_affected() == 0 {
+            return Err(Error::AlreadyReplied);
+        }
+        Ok(())
+    }
+}
+
+/// Reviews waiting for moderation, oldest first
+pub async fn get_pending_reviews(pool: &Pool<Postgres>) -> Result<Vec<Review>, sqlx::Error> {
+    sqlx::query_as!(
+        Review,
+        r#"SELECT
+            reviewid,
+            applicationid,
+            author "author: ReviewAuthor",
+            rating,
+            comment,
+            status "status: ReviewStatus",
+            creation_time,
+            reply,
+            replied_at
+        FROM reviews
+        WHERE status = 'pending'
+        ORDER BY creation_time"#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ModerateReviewRequest {
+    reviewid: i32,
+    approve: bool,
+}
+impl ModerateReviewRequest {
+    pub async fn execute(&self, pool: &Pool<Postgres>) -> Result<(), Error> {
+        let status = if self.approve {
+            ReviewStatus::Approved
+        } else {
+            ReviewStatus::Rejected
+        };
+        let subject = sqlx::query_scalar!(
+            r#"UPDATE reviews SET status = $2, moderated_at = $3
+            WHERE reviewid = $1 AND status = 'pending'
+            RETURNING subject"#,
+            self.reviewid,
+            status as _,
+            OffsetDateTime::now_utc()
+        )
+        .fetch_optional(pool)
+        .await?
+        .ok_or(Error::Forbidden)?;
+        if self.approve {
+            notify(
+                pool,
+                subject,
+                Event::ReviewReceived {
+                    reviewid: self.reviewid,
+                },
+            )
+            .await;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,506 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use time::{Date, Duration, Month, OffsetDateTime, PrimitiveDateTime, Time, Weekday};
+use uuid::Uuid;
+
+use crate::error::Error;
+use crate::jobs::{get_job, JobHours};
+use crate::notifications::{notify, Event};
+use crate::users::{age_on, ADULT_AGE};
+
+/// Working time limits are counted in local days and weeks
+pub(crate) const TIME_ZONE: &str = "Europe/Warsaw";
+const MAX_SHIFT_LENGTH: Duration = Duration::hours(12);
+
+// Limits for juvenile workers from art. 202 and 203 of the Labour Code
+const YOUNG_AGE: i32 = 16;
+const SCHOOL_DAY_LIMIT: Duration = Duration::hours(2);
+const FREE_DAY_LIMIT: Duration = Duration::hours(7);
+const YOUNG_DAY_LIMIT: Duration = Duration::hours(6);
+const SCHOOL_WEEK_LIMIT: Duration = Duration::hours(12);
+const FREE_WEEK_LIMIT: Duration = Duration::hours(35);
+const NIGHT_END: Time = time::macros::time!(6:00);
+const NIGHT_START: Time = time::macros::time!(22:00);
+/// Art. 203 § 2¹, the night of juveniles under `YOUNG_AGE` starts earlier
+const YOUNG_NIGHT_START: Time = time::macros::time!(20:00);
+
+/// Summer and Christmas breaks, winter breaks differ between voivodeships and count as school time
+fn is_school_break(date: Date) -> bool {
+    let june_30 = Date::from_calendar_date(date.year(), Month::June, 30).unwrap_or(date);
+    let last_friday =
+        june_30 - Duration::days(((june_30.weekday().number_days_from_monday() + 3) % 7) as i64);
+    date > last_friday && date.month() < Month::September
+        || date.month() == Month::December && date.day() >= 23
+        || date.month() == Month::January && date.day() == 1
+}
+
+fn is_school_day(date: Date) -> bool {
+    !matches!(date.weekday(), Weekday::Saturday | Weekday::Sunday) && !is_school_break(date)
+}
+
+fn same_week(a: Date, b: Date) -> bool {
+    let (a_year, a_week, _) = a.to_iso_week_date();
+    let (b_year, b_week, _) = b.to_iso_week_date();
+    (a_year, a_week) == (b_year, b_week)
+}
+
+/// Checks a new shift, in local time, against the shifts the candidate already signed up for.
+/// Candidates without a birth date are treated as minors
+pub fn check_working_time(
+    birth_date: Option<Date>,
+    booked: &[(PrimitiveDateTime, PrimitiveDateTime)],
+    shift: (PrimitiveDateTime, PrimitiveDateTime),
+) -> Result<(), Error> {
+    let (start, end) = shift;
+    if booked.iter().any(|(s, e)| *s < end && start < *e) {
+        return Err(Error::ShiftOverlaps);
+    }
+    let worked: Vec<_> = booked.iter().map(|(s, e)| (s.date(), *e - *s)).collect();
+    check_juvenile_limits(birth_date, &worked, start, end, end - start)
+}
+
+/// Checks `length` of work between `start` and `end`, in local time, against the limits for
+/// juvenile workers, `worked` is the time already worked or booked per day.
+/// Without a birth date the limits of the youngest are applied
+pub(crate) fn check_juvenile_limits(
+    birth_date: Option<Date>,
+    worked: &[(Date, Duration)],
+    start: PrimitiveDateTime,
+    end: PrimitiveDateTime,
+    length: Duration,
+) -> Result<(), Error> {
+    let date = start.date();
+    let age = birth_date.map(|b| age_on(b, date));
+    if age.is_some_and(|a| a >= ADULT_AGE) {
+        return Ok(());
+    }
+
+    let young = age.is_none_or(|a| a < YOUNG_AGE);
+
+    let night_start = if young {
+        YOUNG_NIGHT_START
+    } else {
+        NIGHT_START
+    };
+    if start.time() < NIGHT_END || end > PrimitiveDateTime::new(date, night_start) {
+        return Err(Error::WorkingTimeExceeded);
+    }
+    let mut day_limit = if is_school_day(date) {
+        SCHOOL_DAY_LIMIT
+    } else {
+        FREE_DAY_LIMIT
+    };
+    if young {
+        day_limit = day_limit.min(YOUNG_DAY_LIMIT);
+    }
+    let monday = date - Duration::days(date.weekday().number_days_from_monday() as i64);
+    let week_limit = if (0..5).any(|d| is_school_day(monday + Duration::days(d))) {
+        SCHOOL_WEEK_LIMIT
+    } else {
+        FREE_WEEK_LIMIT
+    };
+
+    let day: Duration = worked
+        .iter()
+        .filter(|(d, _)| *d == date)
+        .map(|(_, l)| *l)
+        .sum();
+    let week: Duration = worked
+        .iter()
+        .filter(|(d, _)| same_week(*d, date))
+        .map(|(_, l)| *l)
+        .sum();
+    if day + length > day_limit || week + length > week_limit {
+        return Err(Error::WorkingTimeExceeded);
+    }
+    Ok(())
+}
+
+/// Whether a job with declared rather than dated hours fits the limits for juvenile workers
+/// in the week from `date`. A school day leaves too few hours for a job on working days,
+/// so minors only take those during breaks
+pub(crate) fn declared_hours_allowed(
+    birth_date: Option<Date>,
+    hours: &JobHours,
+    date: Date,
+) -> bool {
+    if birth_date.is_some_and(|b| age_on(b, date) >= ADULT_AGE) {
+        return true;
+    }
+    match hours {
+        JobHours::Week => !(0..7).any(|d| is_school_day(date + Duration::days(d))),
+        JobHours::Weekend | JobHours::Holiday | JobHours::Elastic | JobHours::Shifts => true,
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Shift {
+    shiftid: i32,
+    jobid: i32,
+    start_time: OffsetDateTime,
+    end_time: OffsetDateTime,
+    capacity: i32,
+    /// Number of candidates signed up
+    taken: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AddShiftRequest {
+    jobid: i32,
+    #[serde(with = "time::serde::rfc3339")]
+    start_time: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    end_time: OffsetDateTime,
+    capacity: i32,
+}
+impl AddShiftRequest {
+    pub async fn execute(&self, pool: &Pool<Postgres>, owner: Uuid) -> Result<Shift, Error> {
+        match get_job(pool, self.jobid).await? {
+            Some(job) if job.owner == owner => {
+                if job.archived_at.is_some() {
+                    return Err(Error::JobArchived);
+                }
+            }
+            _ => return Err(Error::Forbidden),
+        }
+        if self.start_time <= OffsetDateTime::now_utc()
+            || self.end_time <= self.start_time
+            || self.end_time - self.start_time > MAX_SHIFT_LENGTH
+            || self.capacity < 1
+        {
+            return Err(Error::ImproperShift);
+        }
+        Ok(sqlx::query_as!(
+            Shift,
+            r#"INSERT INTO shifts (jobid, start_time, end_time, capacity)
+            VALUES ($1, $2, $3, $4)
+            RETURNING shiftid, jobid, start_time, end_time, capacity, 0::bigint "taken!""#,
+            self.jobid,
+            self.start_time,
+            self.end_time,
+            self.capacity
+        )
+        .fetch_one(pool)
+        .await?)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct JobShiftsRequest {
+    jobid: i32,
+}
+impl JobShiftsRequest {
+    /// Shifts of the job which haven't started yet
+    pub async fn execute(&self, pool: &Pool<Postgres>) -> Result<Vec<Shift>, sqlx::Error> {
+        sqlx::query_as!(
+            Shift,
+            r#"SELECT
+                shifts.shiftid,
+                shifts.jobid,
+                shifts.start_time,
+                shifts.end_time,
+                shifts.capacity,
+                count(shift_signups.userid) "taken!"
+            FROM shifts
+            LEFT JOIN shift_signups ON shift_signups.shiftid = shifts.shiftid
+            WHERE shifts.jobid = $1 AND shifts.start_time > now()
+            GROUP BY shifts.shiftid
+            ORDER BY shifts.start_time"#,
+            self.jobid
+        )
+        .fetch_all(pool)
+        .await
+    }
+}
+
+pub async fn get_user_shifts(
+    pool: &Pool<Postgres>,
+    userid: Uuid,
+) -> Result<Vec<Shift>, sqlx::Error> {
+    sqlx::query_as!(
+        Shift,
+        r#"SELECT
+            shifts.shiftid,
+            shifts.jobid,
+            shifts.start_time,
+            shifts.end_time,
+            shifts.capacity,
+            (SELECT count(*) FROM shift_signups s WHERE s.shiftid = shifts.shiftid) "taken!"
+        FROM shift_signups
+        JOIN shifts ON shifts.shiftid = shift_signups.shiftid
+        WHERE shift_signups.userid = $1
+        ORDER BY shifts.start_time"#,
+        userid
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[derive(Debug, Serialize)]
+pub struct ShiftSignup {
+    userid: Uuid,
+    full_name: String,
+    email: String,
+    creation_time: OffsetDateTime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ShiftRequest {
+    shiftid: i32,
+}
+impl ShiftRequest {
+    pub async fn sign_up(&self, pool: &Pool<Postgres>, userid: Uuid) -> Result<(), Error> {
+        let mut tx = pool.begin().await?;
+        // Locking the shift and the candidate keeps concurrent sign-ups from passing the checks together
+        let shift = sqlx::query!(
+            r#"SELECT
+                shifts.jobid,
+                shifts.capacity,
+                shifts.start_time,
+                shifts.start_time AT TIME ZONE $2 "local_start!",
+                shifts.end_time AT TIME ZONE $2 "local_end!",
+                jobs.owner,
+                jobs.archived_at
+            FROM shifts
+            JOIN jobs ON jobs.jobid = shifts.jobid
+            WHERE shifts.shiftid = $1
+            FOR UPDATE OF shifts"#,
+            self.shiftid,
+            TIME_ZONE
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(Error::Forbidden)?;
+        if shift.archived_at.is_some() || shift.start_time <= OffsetDateTime::now_utc() {
+            return Err(Error::ShiftClosed);
+        }
+        let taken = sqlx::query_scalar!(
+            r#"SELECT count(*) "taken!" FROM shift_signups WHERE shiftid = $1"#,
+            self.shiftid
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        if taken >= shift.capacity as i64 {
+            return Err(Error::ShiftFull);
+        }
+
+        let birth_date = sqlx::query_scalar!(
+            "SELECT birth_date FROM users WHERE userid = $1 FOR UPDATE",
+            userid
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        let booked = sqlx::query!(
+            r#"SELECT
+                shifts.start_time AT TIME ZONE $2 "local_start!",
+                shifts.end_time AT TIME ZONE $2 "local_end!"
+            FROM shift_signups
+            JOIN shifts ON shifts.shiftid = shift_signups.shiftid
+            WHERE shift_signups.userid = $1 AND
+                shifts.start_time BETWEEN $3::timestamptz - interval '8 days' AND $3::timestamptz + interval '8 days'"#,
+            userid,
+            TIME_ZONE,
+            shift.start_time
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        let booked: Vec<_> = booked
+            .into_iter()
+            .map(|b| (b.local_start, b.local_end))
+            .collect();
+        check_working_time(birth_date, &booked, (shift.local_start, shift.local_end))?;
+
+        sqlx::query!(
+            r#"INSERT INTO shift_signups (shiftid, userid, creation_time)
+            VALUES ($1, $2, $3)"#,
+            self.shiftid,
+            userid,
+            OffsetDateTime::now_utc()
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        notify(
+            pool,
+            shift.owner,
+            Event::ShiftSignup {
+                shiftid: self.shiftid,
+                jobid: shift.jobid,
+            },
+        )
+        .await;
+        Ok(())
+    }
+    pub async fn withdraw(&self, pool: &Pool<Postgres>, userid: Uuid) -> Result<(), Error> {
+        let withdrawn = sqlx::query!(
+            r#"DELETE FROM shift_signups
+            USING shifts, jobs
+            WHERE shifts.shiftid = shift_signups.shiftid AND
+                jobs.jobid = shifts.jobid AND
+                shift_signups.shiftid = $1 AND
+                shift_signups.userid = $2 AND
+                shifts.start_time > now()
+            RETURNING jobs.jobid, jobs.owner"#,
+            self.shiftid,
+            userid
+        )
+        .fetch_optional(pool)
+        .await?
+        .ok_or(Error::ShiftClosed)?;
+        notify(
+            pool,
+            withdrawn.owner,
+            Event::ShiftWithdrawal {
+                shiftid: self.shiftid,
+                jobid: withdrawn.jobid,
+            },
+        )
+        .await;
+        Ok(())
+    }
+    /// Candidates signed up for one of the company's shifts
+    pub async fn get_signups(
+        &self,
+        pool: &Pool<Postgres>,
+        owner: Uuid,
+    ) -> Result<Vec<ShiftSignup>, sqlx::Error> {
+        sqlx::query_as!(
+            ShiftSignup,
+            r#"SELECT users.userid, users.full_name, users.email, shift_signups.creation_time
+            FROM shift_signups
+            JOIN users ON users.userid = shift_signups.userid
+            JOIN shifts ON shifts.shiftid = shift_signups.shiftid
+            JOIN jobs ON jobs.jobid = shifts.jobid
+            WHERE shift_signups.shiftid = $1 AND jobs.owner = $2
+            ORDER BY shift_signups.creation_time"#,
+            self.shiftid,
+            owner
+        )
+        .fetch_all(pool)
+        .await
+    }
+    /// Removes the shift, candidates signed up for it are notified
+    pub async fn delete(&self, pool: &Pool<Postgres>, owner: Uuid) -> Result<(), Error> {
+        let mut tx = pool.begin().await?;
+        let jobid = sqlx::query_scalar!(
+            r#"SELECT shifts.jobid
+            FROM shifts
+            JOIN jobs ON jobs.jobid = shifts.jobid
+            WHERE shifts.shiftid = $1 AND jobs.owner = $2
+            FOR UPDATE OF shifts"#,
+            self.shiftid,
+            owner
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(Error::Forbidden)?;
+        let signed_up = sqlx::query_scalar!(
+            "SELECT userid FROM shift_signups WHERE shiftid = $1",
+            self.shiftid
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        sqlx::query!("DELETE FROM shifts WHERE shiftid = $1", self.shiftid)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        for userid in signed_up {
+            notify(
+                pool,
+                userid,
+                Event::ShiftCancelled {
+                    shiftid: self.shiftid,
+                    jobid,
+                },
+            )
+            .await;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::{date, datetime};
+
+    /// Born so they are this old on the dates used below
+    fn born(age: i32) -> Option<Date> {
+        Some(Date::from_calendar_date(2026 - age, Month::January, 1).unwrap())
+    }
+
+    fn check(
+        birth_date: Option<Date>,
+        worked: &[(Date, Duration)],
+        start: PrimitiveDateTime,
+        end: PrimitiveDateTime,
+    ) -> Result<(), Error> {
+        check_juvenile_limits(birth_date, worked, start, end, end - start)
+    }
+
+    #[test]
+    fn adults_are_not_limited() {
+        let start = datetime!(2026-10-19 22:00);
+        assert!(check(born(18), &[], start, start + Duration::hours(10)).is_ok());
+    }
+
+    #[test]
+    fn school_days_are_limited_to_two_hours() {
+        let start = datetime!(2026-10-19 10:00);
+        assert!(check(born(17), &[], start, start + Duration::hours(2)).is_ok());
+        assert!(check(born(17), &[], start, start + Duration::hours(3)).is_err());
+        let worked = [(date!(2026 - 10 - 19), Duration::hours(1))];
+        assert!(check(born(17), &worked, start, start + Duration::hours(2)).is_err());
+    }
+
+    #[test]
+    fn school_weeks_are_limited_to_twelve_hours() {
+        let worked = [
+            (date!(2026 - 10 - 24), Duration::hours(7)),
+            (date!(2026 - 10 - 25), Duration::hours(4)),
+        ];
+        let start = datetime!(2026-10-19 10:00);
+        assert!(check(born(17), &worked, start, start + Duration::hours(1)).is_ok());
+        assert!(check(born(17), &worked, start, start + Duration::hours(2)).is_err());
+    }
+
+    #[test]
+    fn the_young_work_shorter_days() {
+        let start = datetime!(2026-10-24 08:00);
+        assert!(check(born(17), &[], start, start + Duration::hours(7)).is_ok());
+        assert!(check(born(15), &[], start, start + Duration::hours(7)).is_err());
+        assert!(check(born(15), &[], start, start + Duration::hours(6)).is_ok());
+    }
+
+    #[test]
+    fn the_night_of_the_young_starts_at_eight() {
+        let start = datetime!(2026-10-24 20:00);
+        assert!(check(born(17), &[], start, start + Duration::hours(2)).is_ok());
+        assert!(check(born(17), &[], start, start + Duration::hours(3)).is_err());
+        assert!(check(born(15), &[], start, start + Duration::hours(1)).is_err());
+        let start = datetime!(2026-10-24 05:00);
+        assert!(check(born(17), &[], start, start + Duration::hours(2)).is_err());
+    }
+
+    #[test]
+    fn without_a_birth_date_the_youngest_limits_apply() {
+        let start = datetime!(2026-10-24 08:00);
+        assert!(check(None, &[], start, start + Duration::hours(6)).is_ok());
+        assert!(check(None, &[], start, start + Duration::hours(7)).is_err());
+        let start = datetime!(2026-10-24 19:00);
+        assert!(check(None, &[], start, start + Duration::hours(2)).is_err());
+    }
+
+    #[test]
+    fn minors_take_weekday_jobs_only_during_breaks() {
+        let term = date!(2026 - 10 - 19);
+        let summer = date!(2026 - 07 - 13);
+        assert!(!declared_hours_allowed(born(17), &JobHours::Week, term));
+        assert!(!declared_hours_allowed(None, &JobHours::Week, term));
+        assert!(declared_hours_allowed(born(17), &JobHours::Week, summer));
+        assert!(declared_hours_allowed(born(18), &JobHours::Week, term));
+        assert!(declared_hours_allowed(born(17), &JobHours::Weekend, term));
+    }
+}
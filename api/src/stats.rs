@@ -0,0 +1,365 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{Pool, Postgres};
+use time::{Date, Duration, OffsetDateTime};
+
+use crate::error::Error;
+use crate::jobs::{ContractType, JobMode};
+use crate::users::iso_date;
+
+/// Used when a request has no `from`
+const DEFAULT_RANGE: Duration = Duration::days(90);
+const MAX_RANGE: Duration = Duration::days(3 * 366);
+
+#[derive(Debug, Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Report {
+    /// New accounts by role
+    Registrations,
+    /// Jobs listed right now by contract type, mode and voivodeship, ignores the range
+    ActiveJobs,
+    /// Applications and hires per job
+    Applications,
+    /// Days from application to hiring
+    TimeToHire,
+    /// Moderated reviews and verified companies
+    Moderation,
+}
+impl Report {
+    fn name(&self) -> &'static str {
+        match self {
+            Report::Registrations => "registrations",
+            Report::ActiveJobs => "active_jobs",
+            Report::Applications => "applications",
+            Report::TimeToHire => "time_to_hire",
+            Report::Moderation => "moderation",
+        }
+    }
+}
+
+/// Length of the periods time series are grouped by
+#[derive(Debug, Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Period {
+    Day,
+    #[default]
+    Week,
+    Month,
+}
+impl Period {
+    /// Field name for `date_trunc`
+    fn field(&self) -> &'static str {
+        match self {
+            Period::Day => "day",
+            Period::Week => "week",
+            Period::Month => "month",
+        }
+    }
+}
+
+/// Rows of a report, cells are strings, numbers or null
+#[derive(Debug, Serialize)]
+pub struct Table {
+    columns: Vec<&'static str>,
+    rows: Vec<Vec<Value>>,
+}
+impl Table {
+    /// RFC 4180 text with a header line. Text starting with a formula character is prefixed
+    /// with `'` so spreadsheets don't evaluate it
+    pub fn to_csv(&self) -> String {
+        let mut csv = self.columns.join(",") + "\r\n";
+        for row in &self.rows {
+            let cells: Vec<String> = row.iter().map(csv_cell).collect();
+            csv += &cells.join(",");
+            csv += "\r\n";
+        }
+        csv
+    }
+}
+
+fn csv_cell(value: &Value) -> String {
+    let text = match value {
+        Value::Null => return String::new(),
+        Value::String(s) if s.starts_with(['=', '+', '-', '@']) => format!("'{}", s),
+        Value::String(s) => s.to_owned(),
+        v => return v.to_string(),
+    };
+    if text.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text
+    }
+}
+
+fn date_cell(date: Option<Date>) -> Value {
+    date.map_or(Value::Null, |d| Value::String(d.to_string()))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StatsRequest {
+    report: Report,
+    /// Start of the range, `DEFAULT_RANGE` before `to` by default
+    #[serde(default, with = "iso_date::option")]
+    from: Option<Date>,
+    /// End of the range, today by default
+    #[serde(default, with = "iso_date::option")]
+    to: Option<Date>,
+    #[serde(default)]
+    period: Period,
+}
+impl StatsRequest {
+    /// Name for the exported file
+    pub fn file_name(&self) -> String {
+        format!("{}.csv", self.report.name())
+    }
+    pub async fn execute(&self, pool: &Pool<Postgres>) -> Result<Table, Error> {
+        let to = self.to.unwrap_or_else(|| OffsetDateTime::now_utc().date());
+        let from = self.from.unwrap_or(to - DEFAULT_RANGE);
+        if from > to || to - from > MAX_RANGE {
+            return Err(Error::ImproperRange);
+        }
+        let period = self.period.field();
+        let table = match self.report {
+            Report::Registrations => registrations(pool, period, from, to).await?,
+            Report::ActiveJobs => active_jobs(pool).await?,
+            Report::Applications => applications(pool, from, to).await?,
+            Report::TimeToHire => time_to_hire(pool, period, from, to).await?,
+            Report::Moderation => moderation(pool, period, from, to).await?,
+        };
+        Ok(table)
+    }
+}
+
+/// Accounts created before creation times were recorded are left out
+async fn registrations(
+    pool: &Pool<Postgres>,
+    period: &str,
+    from: Date,
+    to: Date,
+) -> Result<Table, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"SELECT
+            date_trunc($1, creation_time AT TIME ZONE 'UTC')::date period,
+            role::text "role!",
+            count(*) "count!"
+        FROM login
+        WHERE (creation_time AT TIME ZONE 'UTC')::date BETWEEN $2 AND $3
+        GROUP BY 1, 2
+        ORDER BY 1, 2"#,
+        period,
+        from,
+        to
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(Table {
+        columns: vec!["period", "role", "registrations"],
+        rows: rows
+            .into_iter()
+            .map(|r| vec![date_cell(r.period), r.role.into(), r.count.into()])
+            .collect(),
+    })
+}
+
+async fn active_jobs(pool: &Pool<Postgres>) -> Result<Table, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"SELECT
+            contract_type "contract_type: ContractType",
+            mode "mode: JobMode",
+            voivodeship
+        FROM jobs
+        WHERE archived_at IS NULL AND expires_at > now()"#
+    )
+    .fetch_all(pool)
+    .await?;
+    // A handful of groups, so they are counted here rather than in three queries
+    let mut counts: Vec<(&'static str, Value, i64)> = vec![];
+    let mut count = |dimension: &'static str, value: Value| match counts
+        .iter_mut()
+        .find(|(d, v, _)| *d == dimension && *v == value)
+    {
+        Some((_, _, n)) => *n += 1,
+        None => counts.push((dimension, value, 1)),
+    };
+    for row in rows {
+        count("contract_type", format!("{:?}", row.contract_type).into());
+        count("mode", format!("{:?}", row.mode).into());
+        count(
+            "voivodeship",
+            row.voivodeship.map_or(Value::Null, Value::from),
+        );
+    }
+    counts.sort_by(|a, b| a.0.cmp(b.0).then(b.2.cmp(&a.2)));
+    Ok(Table {
+        columns: vec!["dimension", "value", "jobs"],
+        rows: counts
+            .into_iter()
+            .map(|(dimension, value, n)| vec![dimension.into(), value, n.into()])
+            .collect(),
+    })
+}
+
+/// Applications sent within the range, per job
+async fn applications(pool: &Pool<Postgres>, from: Date, to: Date) -> Result<Table, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"SELECT
+            jobs.jobid,
+            companies.company_name,
+            jobs.job_location,
+            count(*) "applications!",
+            count(applications.hired_at) "hires!"
+        FROM applications
+        JOIN jobs ON jobs.jobid = applications.jobid
+        JOIN companies ON companies.userid = jobs.owner
+        WHERE (applications.creation_time AT TIME ZONE 'UTC')::date BETWEEN $1 AND $2
+        GROUP BY jobs.jobid, companies.company_name
+        ORDER BY 4 DESC, jobs.jobid"#,
+        from,
+        to
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(Table {
+        columns: vec![
+            "jobid",
+            "company_name",
+            "job_location",
+            "applications",
+            "hires",
+        ],
+        rows: rows
+            .into_iter()
+            .map(|r| {
+                vec![
+                    r.jobid.into(),
+                    r.company_name.into(),
+                    r.job_location.into(),
+                    r.applications.into(),
+                    r.hires.into(),
+                ]
+            })
+            .collect(),
+    })
+}
+
+/// Hires within the range, grouped by the period of hiring
+async fn time_to_hire(
+    pool: &Pool<Postgres>,
+    period: &str,
+    from: Date,
+    to: Date,
+) -> Result<Table, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"SELECT
+            date_trunc($1, hired_at AT TIME ZONE 'UTC')::date period,
+            count(*) "hires!",
+            avg(extract(epoch FROM hired_at - creation_time) / 86400)::float8 average_days,
+            percentile_cont(0.5) WITHIN GROUP
+                (ORDER BY extract(epoch FROM hired_at - creation_time) / 86400) median_days
+        FROM applications
+        WHERE (hired_at AT TIME ZONE 'UTC')::date BETWEEN $2 AND $3
+        GROUP BY 1
+        ORDER BY 1"#,
+        period,
+        from,
+        to
+    )
+    .fetch_all(pool)
+    .await?;
+    let days = |d: Option<f64>| d.map_or(Value::Null, |d| ((d * 10.0).round() / 10.0).into());
+    Ok(Table {
+        columns: vec!["period", "hires", "average_days", "median_days"],
+        rows: rows
+            .into_iter()
+            .map(|r| {
+                vec![
+                    date_cell(r.period),
+                    r.hires.into(),
+                    days(r.average_days),
+                    days(r.median_days),
+                ]
+            })
+            .collect(),
+    })
+}
+
+async fn moderation(
+    pool: &Pool<Postgres>,
+    period: &str,
+    from: Date,
+    to: Date,
+) -> Result<Table, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"WITH events (time, kind) AS (
+            SELECT creation_time, 'submitted' FROM reviews
+            UNION ALL
+            SELECT moderated_at, status::text FROM reviews WHERE moderated_at IS NOT NULL
+            UNION ALL
+            SELECT verified_at, 'verified' FROM companies WHERE verified_at IS NOT NULL
+        )
+        SELECT
+            date_trunc($1, time AT TIME ZONE 'UTC')::date period,
+            count(*) FILTER (WHERE kind = 'submitted') "submitted!",
+            count(*) FILTER (WHERE kind = 'approved') "approved!",
+            count(*) FILTER (WHERE kind = 'rejected') "rejected!",
+            count(*) FILTER (WHERE kind = 'verified') "verified_companies!"
+        FROM events
+        WHERE (time AT TIME ZONE 'UTC')::date BETWEEN $2 AND $3
+        GROUP BY 1
+        ORDER BY 1"#,
+        period,
+        from,
+        to
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(Table {
+        columns: vec![
+            "period",
+            "reviews_submitted",
+            "reviews_approved",
+            "reviews_rejected",
+            "companies_verified",
+        ],
+        rows: rows
+            .into_iter()
+            .map(|r| {
+                vec![
+                    date_cell(r.period),
+                    r.submitted.into(),
+                    r.approved.into(),
+                    r.rejected.into(),
+                    r.verified_companies.into(),
+                ]
+            })
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn plain_values_are_written_as_is() {
+        assert_eq!(csv_cell(&Value::Null), "");
+        assert_eq!(csv_cell(&json!(12)), "12");
+        assert_eq!(csv_cell(&json!(true)), "true");
+        assert_eq!(csv_cell(&json!("Kraków")), "Kraków");
+    }
+
+    #[test]
+    fn separators_and_quotes_are_quoted() {
+        assert_eq!(csv_cell(&json!("a,b")), "\"a,b\"");
+        assert_eq!(csv_cell(&json!("say \"hi\"")), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_cell(&json!("a\nb")), "\"a\nb\"");
+    }
+
+    #[test]
+    fn formulas_are_escaped() {
+        assert_eq!(csv_cell(&json!("=SUM(A1)")), "'=SUM(A1)");
+        assert_eq!(csv_cell(&json!("-1")), "'-1");
+        assert_eq!(csv_cell(&json!("@x,y")), "\"'@x,y\"");
+    }
+}
@@ -0,0 +1,307 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{types::JsonValue, PgExecutor, Pool, Postgres};
+
+use crate::error::Error;
+use crate::locations::fold;
+
+const MAX_TAGS: usize = 20;
+const MAX_TAG_LENGTH: usize = 50;
+
+/// Lowercase form without diacritics and punctuation, variants with the same slug are one tag
+pub fn slugify(tag: &str) -> String {
+    let mut slug = String::new();
+    for c in fold(tag).chars() {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_owned()
+}
+
+pub fn validate_tags(tags: &[String]) -> Result<(), Error> {
+    if tags.len() > MAX_TAGS
+        || tags
+            .iter()
+            .any(|t| slugify(t).is_empty() || t.chars().count() > MAX_TAG_LENGTH)
+    {
+        return Err(Error::ImproperTags);
+    }
+    Ok(())
+}
+
+/// Strings of a JSON array, anything else is ignored
+pub fn tag_strings(tags: &Option<JsonValue>) -> Vec<String> {
+    tags.as_ref()
+        .and_then(|t| t.as_array())
+        .map(|t| {
+            t.iter()
+                .filter_map(|v| v.as_str().map(|s| s.to_owned()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Tag {
+    pub(crate) tagid: i32,
+    pub(crate) slug: String,
+    pub(crate) name: String,
+    pub(crate) category: Option<String>,
+}
+
+/// The tag with this slug, or the tag it is a synonym of
+async fn find_tag(pool: &Pool<Postgres>, slug: &str) -> Result<Option<Tag>, sqlx::Error> {
+    sqlx::query_as!(
+        Tag,
+        r#"SELECT tags.tagid, tags.slug, tags.name, tags.category
+        FROM tags
+        LEFT JOIN tag_synonyms ON tag_synonyms.tagid = tags.tagid
+        WHERE tags.slug = $1 OR tag_synonyms.synonym = $1
+        LIMIT 1"#,
+        slug
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// The canonical tags, tags not known yet are created
+pub async fn resolve_tags(pool: &Pool<Postgres>, tags: &[String]) -> Result<Vec<Tag>, sqlx::Error> {
+    let mut resolved: Vec<Tag> = Vec::new();
+    for name in tags {
+        let slug = slugify(name);
+        if slug.is_empty() {
+            continue;
+        }
+        let tag = match find_tag(pool, &slug).await? {
+            Some(t) => t,
+            None => {
+                sqlx::query_as!(
+                    Tag,
+                    r#"INSERT INTO tags (slug, name)
+                    VALUES ($1, $2)
+                    ON CONFLICT (slug) DO UPDATE SET slug = EXCLUDED.slug
+                    RETURNING tagid, slug, name, category"#,
+                    slug,
+                    name.trim()
+                )
+                .fetch_one(pool)
+                .await?
+            }
+        };
+        if !resolved.iter().any(|t| t.tagid == tag.tagid) {
+            resolved.push(tag);
+        }
+    }
+    Ok(resolved)
+}
+
+/// Names of the canonical tags, unknown tags are kept as they are and so match no job
+pub async fn canonical_names(
+    pool: &Pool<Postgres>,
+    tags: &[String],
+) -> Result<Vec<String>, sqlx::Error> {
+    let mut names = Vec::new();
+    for name in tags {
+        match find_tag(pool, &slugify(name)).await? {
+            Some(t) => names.push(t.name),
+            None => names.push(name.to_owned()),
+        }
+    }
+    Ok(names)
+}
+
+pub async fn link_job_tags(
+    executor: impl PgExecutor<'_>,
+    jobid: i32,
+    tags: &[Tag],
+) -> Result<(), sqlx::Error> {
+    let tagids: Vec<i32> = tags.iter().map(|t| t.tagid).collect();
+    sqlx::query!(
+        r#"INSERT INTO job_tags (jobid, tagid)
+        SELECT $1, unnest($2::int[])
+        ON CONFLICT DO NOTHING"#,
+        jobid,
+        &tagids
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Normalises the tags of jobs posted before the vocabulary existed
+pub async fn backfill_job_tags(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    let jobs = sqlx::query!(
+        r#"SELECT jobid, tags
+        FROM jobs
+        WHERE jsonb_typeof(tags) = 'array' AND
+            jsonb_array_length(tags) > 0 AND
+            NOT EXISTS (SELECT 1 FROM job_tags WHERE job_tags.jobid = jobs.jobid)"#
+    )
+    .fetch_all(pool)
+    .await?;
+    for job in jobs {
+        let tags = resolve_tags(pool, &tag_strings(&job.tags)).await?;
+        let names: Vec<String> = tags.iter().map(|t| t.name.to_owned()).collect();
+        sqlx::query!(
+            "UPDATE jobs SET tags = $2 WHERE jobid = $1",
+            job.jobid,
+            JsonValue::from(names)
+        )
+        .execute(pool)
+        .await?;
+        link_job_tags(pool, job.jobid, &tags).await?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+pub struct TagDetails {
+    tagid: i32,
+    slug: String,
+    name: String,
+    category: Option<String>,
+    synonyms: Vec<String>,
+    uses: i64,
+}
+
+pub async fn get_tag_details(pool: &Pool<Postgres>) -> Result<Vec<TagDetails>, sqlx::Error> {
+    sqlx::query_as!(
+        TagDetails,
+        r#"SELECT
+            tags.tagid,
+            tags.slug,
+            tags.name,
+            tags.category,
+            coalesce(
+                array_agg(DISTINCT tag_synonyms.synonym)
+                    FILTER (WHERE tag_synonyms.synonym IS NOT NULL),
+                '{}'
+            ) "synonyms!",
+            count(DISTINCT job_tags.jobid) "uses!"
+        FROM tags
+        LEFT JOIN tag_synonyms ON tag_synonyms.tagid = tags.tagid
+        LEFT JOIN job_tags ON job_tags.tagid = tags.tagid
+        GROUP BY tags.tagid
+        ORDER BY tags.slug"#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SynonymRequest {
+    tag: String,
+    synonym: String,
+}
+impl SynonymRequest {
+    /// Makes `synonym` an alias of `tag`, if `synonym` is a tag of its own it is merged into `tag`
+    pub async fn add(&self, pool: &Pool<Postgres>) -> Result<(), Error> {
+        let target = match find_tag(pool, &slugify(&self.tag)).await? {
+            Some(t) => t,
+            None => return Err(Error::NoSuchTag),
+        };
+        let slug = slugify(&self.synonym);
+        if slug.is_empty() {
+            return Err(Error::ImproperTags);
+        }
+        if slug == target.slug {
+            return Ok(());
+        }
+
+        let mut tx = pool.begin().await?;
+        let merged = sqlx::query_as!(
+            Tag,
+            "SELECT tagid, slug, name, category FROM tags WHERE slug = $1",
+            slug
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+        if let Some(old) = merged {
+            sqlx::query!(
+                r#"INSERT INTO job_tags (jobid, tagid)
+                SELECT jobid, $2 FROM job_tags WHERE tagid = $1
+                ON CONFLICT DO NOTHING"#,
+                old.tagid,
+                target.tagid
+            )
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query!(
+                r#"UPDATE jobs SET tags = (
+                    SELECT coalesce(jsonb_agg(DISTINCT CASE WHEN t = $2 THEN $3 ELSE t END), '[]')
+                    FROM jsonb_array_elements_text(jobs.tags) t
+                )
+                WHERE jobid IN (SELECT jobid FROM job_tags WHERE tagid = $1)"#,
+                old.tagid,
+                old.name,
+                target.name
+            )
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query!("DELETE FROM job_tags WHERE tagid = $1", old.tagid)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query!(
+                "UPDATE tag_synonyms SET tagid = $2 WHERE tagid = $1",
+                old.tagid,
+                target.tagid
+            )
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query!("DELETE FROM tags WHERE tagid = $1", old.tagid)
+                .execute(&mut *tx)
+                .await?;
+        }
+        sqlx::query!(
+            r#"INSERT INTO tag_synonyms (synonym, tagid)
+            VALUES ($1, $2)
+            ON CONFLICT (synonym) DO UPDATE SET tagid = $2"#,
+            slug,
+            target.tagid
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RemoveSynonymRequest {
+    synonym: String,
+}
+impl RemoveSynonymRequest {
+    pub async fn execute(&self, pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "DELETE FROM tag_synonyms WHERE synonym = $1",
+            slugify(&self.synonym)
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CategoryRequest {
+    tag: String,
+    category: Option<String>,
+}
+impl CategoryRequest {
+    pub async fn execute(&self, pool: &Pool<Postgres>) -> Result<(), Error> {
+        let tag = match find_tag(pool, &slugify(&self.tag)).await? {
+            Some(t) => t,
+            None => return Err(Error::NoSuchTag),
+        };
+        sqlx::query!(
+            "UPDATE tags SET category = $2 WHERE tagid = $1",
+            tag.tagid,
+            self.category
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,168 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{types::Json, Pool, Postgres};
+use time::{Duration, OffsetDateTime};
+use uuid::Uuid;
+
+use crate::drafts::JobDraft;
+use crate::error::Error;
+use crate::expiry::MAX_LIFETIME;
+use crate::jobs::{add_job, Job};
+
+#[derive(Debug, Serialize)]
+pub struct Template {
+    templateid: i32,
+    owner: Uuid,
+    name: String,
+    job: Json<JobDraft>,
+    lifetime_days: Option<i32>,
+    creation_time: OffsetDateTime,
+    update_time: OffsetDateTime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SaveTemplateRequest {
+    /// When missing, a template with the same name is replaced or a new one created
+    #[serde(default)]
+    templateid: Option<i32>,
+    name: String,
+    /// Templates are reused long after they are saved, so they can't have a fixed `expires_at`
+    job: JobDraft,
+    /// How long jobs posted from the template stay listed, `expiry::DEFAULT_LIFETIME` when missing
+    #[serde(default)]
+    lifetime_days: Option<i32>,
+}
+impl SaveTemplateRequest {
+    pub async fn execute(&self, pool: &Pool<Postgres>, owner: Uuid) -> Result<Template, Error> {
+        if self.job.expires_at.is_some()
+            || self
+                .lifetime_days
+                .is_some_and(|d| d < 1 || i64::from(d) > MAX_LIFETIME.whole_days())
+        {
+            return Err(Error::ImproperExpiry);
+        }
+        let now = OffsetDateTime::now_utc();
+        let template = match self.templateid {
+            None => Some(
+                sqlx::query_as!(
+                    Template,
+                    r#"INSERT INTO job_templates (owner, name, job, lifetime_days, creation_time, update_time)
+                    VALUES ($1, $2, $3, $5, $4, $4)
+                    ON CONFLICT (owner, name) DO UPDATE SET job = $3, lifetime_days = $5, update_time = $4
+                    RETURNING
+                        templateid,
+                        owner,
+                        name,
+                        job "job: Json<JobDraft>",
+                        lifetime_days,
+                        creation_time,
+                        update_time"#,
+                    owner,
+                    self.name,
+                    Json(&self.job) as _,
+                    now,
+                    self.lifetime_days
+                )
+                .fetch_one(pool)
+                .await?,
+            ),
+            Some(templateid) => {
+                sqlx::query_as!(
+                    Template,
+                    r#"UPDATE job_templates SET name = $3, job = $4, update_time = $5, lifetime_days = $6
+                    WHERE templateid = $1 AND owner = $2
+                    RETURNING
+                        templateid,
+                        owner,
+                        name,
+                        job "job: Json<JobDraft>",
+                        lifetime_days,
+                        creation_time,
+                        update_time"#,
+                    templateid,
+                    owner,
+                    self.name,
+                    Json(&self.job) as _,
+                    now,
+                    self.lifetime_days
+                )
+                .fetch_optional(pool)
+                .await?
+            }
+        };
+        template.ok_or(Error::Forbidden)
+    }
+}
+
+pub async fn get_templates(
+    pool: &Pool<Postgres>,
+    owner: Uuid,
+) -> Result<Vec<Template>, sqlx::Error> {
+    sqlx::query_as!(
+        Template,
+        r#"SELECT
+            templateid,
+            owner,
+            name,
+            job "job: Json<JobDraft>",
+            lifetime_days,
+            creation_time,
+            update_time
+        FROM job_templates
+        WHERE owner = $1
+        ORDER BY name"#,
+        owner
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TemplateRequest {
+    templateid: i32,
+}
+impl TemplateRequest {
+    pub async fn delete(&self, pool: &Pool<Postgres>, owner: Uuid) -> Result<(), Error> {
+        let deleted = sqlx::query!(
+            "DELETE FROM job_templates WHERE templateid = $1 AND owner = $2",
+            self.templateid,
+            owner
+        )
+        .execute(pool)
+        .await?;
+        if deleted.rows_affected() == 0 {
+            return Err(Error::Forbidden);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UseTemplateRequest {
+    templateid: i32,
+    /// Fields replacing the ones stored in the template, `expires_at` replaces its lifetime
+    #[serde(default)]
+    overrides: JobDraft,
+}
+impl UseTemplateRequest {
+    /// Posts a job from the template, job alerts are left to the caller
+    pub async fn execute(&self, pool: &Pool<Postgres>, owner: Uuid) -> Result<Job, Error> {
+        let template = sqlx::query!(
+            r#"SELECT job "job: Json<JobDraft>", lifetime_days
+            FROM job_templates
+            WHERE templateid = $1 AND owner = $2"#,
+            self.templateid,
+            owner
+        )
+        .fetch_optional(pool)
+        .await?
+        .ok_or(Error::Forbidden)?;
+        let mut request = template.job.merge(&self.overrides).complete()?;
+        if request.expires_at.is_none() {
+            if let Some(days) = template.lifetime_days {
+                request.expires_at = Some(OffsetDateTime::now_utc() + Duration::days(days.into()));
+            }
+        }
+        let job = request.into_job(owner)?;
+        Ok(add_job(pool, &job).await?)
+    }
+}
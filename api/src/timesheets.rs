@@ -0,0 +1,448 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use time::{Date, Duration, OffsetDateTime, PrimitiveDateTime, Time};
+use uuid::Uuid;
+
+use crate::applications::Application;
+use crate::error::Error;
+use crate::jobs::{ContractType, PayUnit};
+use crate::notifications::{notify, Event};
+use crate::pay::{estimate_for_age, PayEstimate};
+use crate::shifts::{check_juvenile_limits, TIME_ZONE};
+use crate::users::{age_on, guardian_ward, iso_date};
+
+// Times of day as "HH:MM" in requests and responses
+time::serde::format_description!(clock_time, Time, "[hour]:[minute]");
+
+#[derive(Serialize, Deserialize, Debug, sqlx::Type, Clone, Copy, PartialEq)]
+#[sqlx(type_name = "timesheet_status", rename_all = "lowercase")]
+pub enum TimesheetStatus {
+    Submitted,
+    Approved,
+    Rejected,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TimesheetEntry {
+    entryid: i32,
+    applicationid: i32,
+    #[serde(with = "iso_date")]
+    work_date: Date,
+    #[serde(with = "clock_time")]
+    start_time: Time,
+    #[serde(with = "clock_time")]
+    end_time: Time,
+    break_minutes: i32,
+    /// Time between start and end without the break
+    worked_minutes: i32,
+    status: TimesheetStatus,
+    creation_time: OffsetDateTime,
+    reviewed_at: Option<OffsetDateTime>,
+}
+
+/// Start and end of an entry, one ending at or before its start time ends on the next day
+fn work_period(work_date: Date, start: Time, end: Time) -> (PrimitiveDateTime, PrimitiveDateTime) {
+    let end_date = if end <= start {
+        work_date.next_day().unwrap_or(work_date)
+    } else {
+        work_date
+    };
+    (
+        PrimitiveDateTime::new(work_date, start),
+        PrimitiveDateTime::new(end_date, end),
+    )
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SubmitTimesheetRequest {
+    applicationid: i32,
+    #[serde(with = "iso_date")]
+    work_date: Date,
+    #[serde(with = "clock_time")]
+    start_time: Time,
+    #[serde(with = "clock_time")]
+    end_time: Time,
+    #[serde(default)]
+    break_minutes: i32,
+}
+impl SubmitTimesheetRequest {
+    /// Records the hours the hired candidate worked, for the company to approve.
+    /// Minors can't record more than the law lets them work
+    pub async fn execute(
+        &self,
+        pool: &Pool<Postgres>,
+        userid: Uuid,
+    ) -> Result<TimesheetEntry, Error> {
+        let mut tx = pool.begin().await?;
+        // Locking the candidate keeps concurrent entries from passing the limits together
+        let candidate = sqlx::query!(
+            r#"SELECT birth_date, (now() AT TIME ZONE $2)::date "today!"
+            FROM users
+            WHERE userid = $1
+            FOR UPDATE"#,
+            userid,
+            TIME_ZONE
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        let engagement = sqlx::query!(
+            r#"SELECT
+                applications.userid,
+                (applications.hired_at AT TIME ZONE $2)::date hired_on,
+                jobs.owner
+            FROM applications
+            JOIN jobs ON jobs.jobid = applications.jobid
+            WHERE applications.applicationid = $1"#,
+            self.applicationid,
+            TIME_ZONE
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .filter(|e| e.userid == userid)
+        .ok_or(Error::Forbidden)?;
+        let hired_on = engagement.hired_on.ok_or(Error::NotHired)?;
+
+        let (start, end) = work_period(self.work_date, self.start_time, self.end_time);
+        let length = end - start - Duration::minutes(self.break_minutes as i64);
+        if self.work_date < hired_on
+            || self.work_date > candidate.today
+            || self.break_minutes < 0
+            || length <= Duration::ZERO
+        {
+            return Err(Error::ImproperTimesheet);
+        }
+
+        // Every engagement counts towards the limits, rejected entries don't
+        let recorded = sqlx::query!(
+            r#"SELECT
+                timesheet_entries.work_date,
+                timesheet_entries.start_time,
+                timesheet_entries.end_time,
+                timesheet_entries.break_minutes
+            FROM timesheet_entries
+            JOIN applications ON applications.applicationid = timesheet_entries.applicationid
+            WHERE applications.userid = $1 AND
+                timesheet_entries.status <> 'rejected' AND
+                timesheet_entries.work_date BETWEEN $2::date - 7 AND $2::date + 7"#,
+            userid,
+            self.work_date
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        let periods: Vec<_> = recorded
+            .iter()
+            .map(|r| work_period(r.work_date, r.start_time, r.end_time))
+            .collect();
+        if periods.iter().any(|(s, e)| *s < end && start < *e) {
+            return Err(Error::TimesheetOverlaps);
+        }
+        let worked: Vec<_> = recorded
+            .iter()
+            .zip(&periods)
+            .map(|(r, (s, e))| {
+                (
+                    r.work_date,
+                    *e - *s - Duration::minutes(r.break_minutes as i64),
+                )
+            })
+            .collect();
+        check_juvenile_limits(candidate.birth_date, &worked, start, end, length)?;
+
+        let entry = sqlx::query_as!(
+            TimesheetEntry,
+            r#"INSERT INTO timesheet_entries (
+                applicationid,
+                work_date,
+                start_time,
+                end_time,
+                break_minutes,
+                status,
+                creation_time
+            )
+            VALUES ($1, $2, $3, $4, $5, 'submitted', $6)
+            RETURNING
+                entryid,
+                applicationid,
+                work_date,
+                start_time,
+                end_time,
+                break_minutes,
+                worked_minutes,
+                status "status: TimesheetStatus",
+                creation_time,
+                reviewed_at"#,
+            self.applicationid,
+            self.work_date,
+            self.start_time,
+            self.end_time,
+            self.break_minutes,
+            OffsetDateTime::now_utc()
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        notify(
+            pool,
+            engagement.owner,
+            Event::TimesheetSubmitted {
+                entryid: entry.entryid,
+            },
+        )
+        .await;
+        Ok(entry)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TimesheetRequest {
+    applicationid: i32,
+}
+impl TimesheetRequest {
+    pub async fn execute(
+        &self,
+        pool: &Pool<Postgres>,
+        userid: Uuid,
+    ) -> Result<Vec<TimesheetEntry>, Error> {
+        if !Application::is_participant(pool, self.applicationid, userid).await? {
+            return Err(Error::Forbidden);
+        }
+        Ok(sqlx::query_as!(
+            TimesheetEntry,
+            r#"SELECT
+                entryid,
+                applicationid,
+                work_date,
+                start_time,
+                end_time,
+                break_minutes,
+                worked_minutes,
+                status "status: TimesheetStatus",
+                creation_time,
+                reviewed_at
+            FROM timesheet_entries
+            WHERE applicationid = $1
+            ORDER BY work_date DESC, start_time DESC"#,
+            self.applicationid
+        )
+        .fetch_all(pool)
+        .await?)
+    }
+    /// Approved time per month, with the pay estimated from the accepted offer or else the job
+    pub async fn earnings(
+        &self,
+        pool: &Pool<Postgres>,
+        userid: Uuid,
+    ) -> Result<Vec<MonthlyEarnings>, Error> {
+        if !Application::is_participant(pool, self.applicationid, userid).await? {
+            return Err(Error::Forbidden);
+        }
+        let rate = sqlx::query!(
+            r#"SELECT
+                users.birth_date,
+                COALESCE(offer.contract_type, jobs.contract_type) "contract_type!: ContractType",
+                COALESCE(offer.pay_amount, jobs.pay_amount) pay_amount,
+                COALESCE(offer.pay_unit, jobs.pay_unit) "pay_unit: PayUnit",
+                COALESCE(offer.pay_currency, jobs.pay_currency) pay_currency,
+                COALESCE(offer.pay_gross, jobs.pay_gross) pay_gross
+            FROM applications
+            JOIN users ON users.userid = applications.userid
+            JOIN jobs ON jobs.jobid = applications.jobid
+            LEFT JOIN LATERAL (
+                SELECT contract_type, pay_amount, pay_unit, pay_currency, pay_gross
+                FROM offers
+                WHERE offers.applicationid = applications.applicationid AND
+                    offers.accepted_at IS NOT NULL
+                ORDER BY offers.accepted_at DESC
+                LIMIT 1
+            ) offer ON true
+            WHERE applications.applicationid = $1"#,
+            self.applicationid
+        )
+        .fetch_one(pool)
+        .await?;
+        let hourly = match (
+            rate.pay_amount,
+            rate.pay_unit,
+            rate.pay_currency,
+            rate.pay_gross,
+        ) {
+            (Some(amount), Some(PayUnit::Hour), Some(currency), Some(true))
+                if currency == "PLN" =>
+            {
+                Some(amount)
+            }
+            _ => None,
+        };
+
+        let months = sqlx::query!(
+            r#"SELECT
+                to_char(work_date, 'YYYY-MM') "month!",
+                sum(worked_minutes)::bigint "worked_minutes!",
+                max(work_date) "last_day!"
+            FROM timesheet_entries
+            WHERE applicationid = $1 AND status = 'approved'
+            GROUP BY 1
+            ORDER BY 1 DESC"#,
+            self.applicationid
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(months
+            .into_iter()
+            .map(|m| MonthlyEarnings {
+                estimate: hourly.map(|amount| {
+                    estimate_for_age(
+                        &rate.contract_type,
+                        (amount * m.worked_minutes + 30) / 60,
+                        rate.birth_date.map(|b| age_on(b, m.last_day)),
+                    )
+                }),
+                month: m.month,
+                worked_minutes: m.worked_minutes,
+            })
+            .collect())
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct MonthlyEarnings {
+    /// "YYYY-MM"
+    month: String,
+    worked_minutes: i64,
+    /// Only for gross hourly rates in PLN
+    estimate: Option<PayEstimate>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TimesheetEntryRequest {
+    entryid: i32,
+}
+impl TimesheetEntryRequest {
+    /// Withdraws an entry the company hasn't reviewed yet
+    pub async fn delete(&self, pool: &Pool<Postgres>, userid: Uuid) -> Result<(), Error> {
+        let entry = sqlx::query!(
+            r#"SELECT timesheet_entries.status "status: TimesheetStatus", applications.userid
+            FROM timesheet_entries
+            JOIN applications ON applications.applicationid = timesheet_entries.applicationid
+            WHERE timesheet_entries.entryid = $1"#,
+            self.entryid
+        )
+        .fetch_optional(pool)
+        .await?
+        .filter(|e| e.userid == userid)
+        .ok_or(Error::Forbidden)?;
+        if entry.status != TimesheetStatus::Submitted {
+            return Err(Error::TimesheetReviewed);
+        }
+        sqlx::query!(
+            "DELETE FROM timesheet_entries WHERE entryid = $1 AND status = 'submitted'",
+            self.entryid
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+    pub async fn approve(&self, pool: &Pool<Postgres>, owner: Uuid) -> Result<(), Error> {
+        self.review(pool, owner, TimesheetStatus::Approved).await
+    }
+    pub async fn reject(&self, pool: &Pool<Postgres>, owner: Uuid) -> Result<(), Error> {
+        self.review(pool, owner, TimesheetStatus::Rejected).await
+    }
+    async fn review(
+        &self,
+        pool: &Pool<Postgres>,
+        owner: Uuid,
+        status: TimesheetStatus,
+    ) -> Result<(), Error> {
+        let entry = sqlx::query!(
+            r#"SELECT timesheet_entries.status "status: TimesheetStatus", applications.userid, jobs.owner
+            FROM timesheet_entries
+            JOIN applications ON applications.applicationid = timesheet_entries.applicationid
+            JOIN jobs ON jobs.jobid = applications.jobid
+            WHERE timesheet_entries.entryid = $1"#,
+            self.entryid
+        )
+        .fetch_optional(pool)
+        .await?
+        .filter(|e| e.owner == owner)
+        .ok_or(Error::Forbidden)?;
+        if entry.status != TimesheetStatus::Submitted {
+            return Err(Error::TimesheetReviewed);
+        }
+        let reviewed = sqlx::query!(
+            r#"UPDATE timesheet_entries SET status = $2, reviewed_at = $3
+            WHERE entryid = $1 AND status = 'submitted'"#,
+            self.entryid,
+            status as _,
+            OffsetDateTime::now_utc()
+        )
+        .execute(pool)
+        .await?;
+        if reviewed.rows_affected() == 0 {
+            return Err(Error::TimesheetReviewed);
+        }
+
+        notify(
+            pool,
+            entry.userid,
+            Event::TimesheetReviewed {
+                entryid: self.entryid,
+            },
+        )
+        .await;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GuardianTimesheetRequest {
+    /// From the mail sent when the guardian was set, guardians have no accounts
+    token: Uuid,
+}
+impl GuardianTimesheetRequest {
+    /// Entries of every engagement of the guardian's ward
+    pub async fn execute(&self, pool: &Pool<Postgres>) -> Result<Vec<TimesheetEntry>, Error> {
+        let userid = guardian_ward(pool, self.token).await?;
+        Ok(sqlx::query_as!(
+            TimesheetEntry,
+            r#"SELECT
+                timesheet_entries.entryid,
+                timesheet_entries.applicationid,
+                timesheet_entries.work_date,
+                timesheet_entries.start_time,
+                timesheet_entries.end_time,
+                timesheet_entries.break_minutes,
+                timesheet_entries.worked_minutes,
+                timesheet_entries.status "status: TimesheetStatus",
+                timesheet_entries.creation_time,
+                timesheet_entries.reviewed_at
+            FROM timesheet_entries
+            JOIN applications ON applications.applicationid = timesheet_entries.applicationid
+            WHERE applications.userid = $1
+            ORDER BY timesheet_entries.work_date DESC, timesheet_entries.start_time DESC"#,
+            userid
+        )
+        .fetch_all(pool)
+        .await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::{date, datetime, time};
+
+    #[test]
+    fn entries_past_midnight_end_the_next_day() {
+        let day = date!(2026 - 10 - 19);
+        assert_eq!(
+            work_period(day, time!(9:00), time!(17:00)),
+            (datetime!(2026-10-19 9:00), datetime!(2026-10-19 17:00))
+        );
+        assert_eq!(
+            work_period(day, time!(22:00), time!(2:00)),
+            (datetime!(2026-10-19 22:00), datetime!(2026-10-20 2:00))
+        );
+    }
+}
@@ -13,6 +13,10 @@ use warp::reject::Reject;
 
 use crate::auth::Claim;
 use crate::error::Error;
+use crate::mail::{send_mail, APP_URL};
+
+// Dates as "YYYY-MM-DD" in requests and responses
+time::serde::format_description!(pub iso_date, Date, "[year]-[month]-[day]");
 
 fn get_string_hash(s: &str) -> i64 {
     let mut hasher = DefaultHasher::new();
@@ -26,32 +30,43 @@ pub struct CreateUserRequest {
     full_name: String,
     login: String,
     password: String,
+    /// Without it the user is treated as a minor
+    #[serde(default, with = "iso_date::option")]
+    birth_date: Option<time::Date>,
 }
 impl CreateUserRequest {
-    pub async fn execute(&self, pool: &Pool<Postgres>) -> Result<User, sqlx::Error> {
-        let uuid = uuid::Uuid::new_v7(Timestamp::now(NoContext::default()));
+    pub async fn execute(&self, pool: &Pool<Postgres>) -> Result<User, Error> {
+        if self
+            .birth_date
+            .is_some_and(|d| d >= time::OffsetDateTime::now_utc().date())
+        {
+            return Err(Error::ImproperBirthDate);
+        }
+        let uuid = uuid::Uuid::new_v7(Timestamp::now(NoContext));
 
         sqlx::query!(
-            r#" INSERT INTO login (login, email, password, userid, role)
-            VALUES ($1, $2, $3, $4, $5)"#,
+            r#" INSERT INTO login (login, email, password, userid, role, creation_time)
+            VALUES ($1, $2, $3, $4, $5, $6)"#,
             self.login,
             self.email,
             self.get_password_hash(),
             uuid,
-            UserRole::User as _
+            UserRole::User as _,
+            time::OffsetDateTime::now_utc()
         )
         .execute(pool)
         .await?;
 
         sqlx::query!(
-            r#"INSERT INTO users (email, full_name, login, password, userid)
-            VALUES ($1, $2, $3, $4, $5)
+            r#"INSERT INTO users (email, full_name, login, password, userid, birth_date)
+            VALUES ($1, $2, $3, $4, $5, $6)
             "#,
             self.email,
             self.full_name,
             self.login,
             self.get_password_hash(),
-            uuid
+            uuid,
+            self.birth_date
         )
         .execute(pool)
         .await?;
@@ -62,6 +77,10 @@ impl CreateUserRequest {
             password: self.get_password_hash(),
             email: self.email.to_owned(),
             full_name: self.full_name.to_owned(),
+            birth_date: self.birth_date,
+            guardian_name: None,
+            guardian_email: None,
+            guardian_confirmed_at: None,
         })
     }
     pub fn get_password_hash(&self) -> i64 {
@@ -69,6 +88,141 @@ impl CreateUserRequest {
     }
 }
 
+#[derive(Debug, Deserialize)]
+pub struct SetBirthDateRequest {
+    #[serde(with = "iso_date")]
+    birth_date: time::Date,
+}
+impl SetBirthDateRequest {
+    /// Sets or corrects the birth date, it is fixed once a contract of the user names it
+    pub async fn execute(&self, pool: &Pool<Postgres>, userid: Uuid) -> Result<(), Error> {
+        if self.birth_date >= time::OffsetDateTime::now_utc().date() {
+            return Err(Error::ImproperBirthDate);
+        }
+        let updated = sqlx::query!(
+            r#"UPDATE users SET birth_date = $2
+            WHERE userid = $1 AND NOT EXISTS (
+                SELECT 1 FROM contracts
+                JOIN applications ON applications.applicationid = contracts.applicationid
+                WHERE applications.userid = $1
+            )"#,
+            userid,
+            self.birth_date
+        )
+        .execute(pool)
+        .await?;
+        if updated.rows_affected() == 0 {
+            return Err(Error::AlreadyHired);
+        }
+        Ok(())
+    }
+}
+
+/// Age at which the limits for juvenile workers stop and no guardian is needed
+pub(crate) const ADULT_AGE: i32 = 18;
+
+/// Age in full years on the given day
+pub fn age_on(birth_date: time::Date, date: time::Date) -> i32 {
+    let age = date.year() - birth_date.year();
+    if (date.month() as u8, date.day()) < (birth_date.month() as u8, birth_date.day()) {
+        age - 1
+    } else {
+        age
+    }
+}
+
+/// The statutory representative of a minor, named in their contracts
+#[derive(Debug, Deserialize)]
+pub struct SetGuardianRequest {
+    full_name: String,
+    email: String,
+}
+impl SetGuardianRequest {
+    pub async fn execute(&self, pool: &Pool<Postgres>, userid: Uuid) -> Result<(), Error> {
+        if self.full_name.trim().is_empty() || !self.email.contains('@') {
+            return Err(Error::ImproperGuardian);
+        }
+        // A new token, so a previous guardian loses access until the new one confirms
+        let token = Uuid::new_v4();
+        let mut tx = pool.begin().await?;
+        let full_name = sqlx::query_scalar!(
+            r#"UPDATE users SET
+                guardian_name = $2,
+                guardian_email = $3,
+                guardian_token = $4,
+                guardian_confirmed_at = NULL
+            WHERE userid = $1
+            RETURNING full_name"#,
+            userid,
+            self.full_name.trim(),
+            self.email.trim(),
+            token
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        // Offer links mailed to the previous guardian stop working, the candidate sends them
+        // to the new one with `OfferRequest::resend`
+        sqlx::query!(
+            r#"UPDATE offers SET guardian_token = NULL, guardian_name = NULL, guardian_email = NULL
+            FROM applications
+            WHERE applications.applicationid = offers.applicationid AND
+                applications.userid = $1 AND
+                offers.accepted_at IS NULL"#,
+            userid
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        send_mail(
+            self.email.trim(),
+            "You were named a guardian",
+            format!(
+                "{} named you as their guardian.\n\
+                Confirm it at {}/guardian/confirm/{}\n\
+                Until then you can't accept job offers for them or follow the hours they work.\n",
+                full_name, *APP_URL, token
+            ),
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConfirmGuardianRequest {
+    /// From the mail sent when the guardian was set
+    token: Uuid,
+}
+impl ConfirmGuardianRequest {
+    /// Proves the guardian controls the address the minor gave, confirming again changes nothing
+    pub async fn execute(&self, pool: &Pool<Postgres>) -> Result<(), Error> {
+        let confirmed = sqlx::query!(
+            r#"UPDATE users SET guardian_confirmed_at = coalesce(guardian_confirmed_at, $2)
+            WHERE guardian_token = $1"#,
+            self.token,
+            time::OffsetDateTime::now_utc()
+        )
+        .execute(pool)
+        .await?;
+        if confirmed.rows_affected() == 0 {
+            return Err(Error::Forbidden);
+        }
+        Ok(())
+    }
+}
+
+/// The minor whose guardian holds the token, once the guardian confirmed it
+pub async fn guardian_ward(pool: &Pool<Postgres>, token: Uuid) -> Result<Uuid, Error> {
+    sqlx::query_scalar!(
+        r#"SELECT userid FROM users
+        WHERE guardian_token = $1 AND guardian_confirmed_at IS NOT NULL"#,
+        token
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or(Error::Forbidden)
+}
+
 #[derive(Debug, Deserialize)]
 pub struct CreateCompanyRequest {
     email: String,
@@ -83,16 +237,17 @@ impl CreateCompanyRequest {
         if !self.validate_nip() {
             return Err(Error::ImproperNIP);
         }
-        let uuid = uuid::Uuid::new_v7(Timestamp::now(NoContext::default()));
+        let uuid = uuid::Uuid::new_v7(Timestamp::now(NoContext));
 
         sqlx::query!(
-            r#" INSERT INTO login (login, email, password, userid, role)
-            VALUES ($1, $2, $3, $4, $5)"#,
+            r#" INSERT INTO login (login, email, password, userid, role, creation_time)
+            VALUES ($1, $2, $3, $4, $5, $6)"#,
             self.login,
             self.email,
             self.get_password_hash(),
             uuid,
-            UserRole::Company as _
+            UserRole::Company as _,
+            time::OffsetDateTime::now_utc()
         )
         .execute(pool)
         .await?;
@@ -158,6 +313,7 @@ impl CreateCompanyRequest {
 pub enum UserRole {
     Company,
     User,
+    Admin,
 }
 
 impl std::fmt::Display for UserRole {
@@ -165,6 +321,7 @@ impl std::fmt::Display for UserRole {
         match self {
             Self::Company => write!(f, "Company"),
             Self::User => write!(f, "User"),
+            Self::Admin => write!(f, "Admin"),
         }
     }
 }
@@ -230,6 +387,12 @@ pub struct User {
     pub password: i64,
     pub email: String,
     pub full_name: String,
+    #[serde(with = "iso_date::option")]
+    pub birth_date: Option<time::Date>,
+    pub guardian_name: Option<String>,
+    pub guardian_email: Option<String>,
+    /// When the guardian confirmed their address, minors can't accept offers before
+    pub guardian_confirmed_at: Option<time::OffsetDateTime>,
 }
 
 #[derive(Debug, Clone, sqlx::FromRow, Serialize, Deserialize)]
@@ -243,3 +406,25 @@ pub struct Company {
     pub company_name: String,
     pub full_name: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::date;
+
+    #[test]
+    fn age_changes_on_the_birthday() {
+        let birth_date = date!(2010 - 05 - 04);
+        assert_eq!(age_on(birth_date, date!(2026 - 05 - 03)), 15);
+        assert_eq!(age_on(birth_date, date!(2026 - 05 - 04)), 16);
+        assert_eq!(age_on(birth_date, date!(2026 - 12 - 31)), 16);
+    }
+
+    #[test]
+    fn leap_day_birthdays_count_from_march() {
+        let birth_date = date!(2008 - 02 - 29);
+        assert_eq!(age_on(birth_date, date!(2026 - 02 - 28)), 17);
+        assert_eq!(age_on(birth_date, date!(2026 - 03 - 01)), 18);
+        assert_eq!(age_on(birth_date, date!(2028 - 02 - 29)), 20);
+    }
+}